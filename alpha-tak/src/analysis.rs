@@ -2,7 +2,10 @@ use std::fmt::{Display, Write};
 
 use tak::*;
 
-use crate::search::{MoveInfo, Node};
+use crate::{
+    model::score::win_probability,
+    search::{MoveInfo, NodeRef},
+};
 
 const MAX_BRANCH_LENGTH: usize = 10;
 const BRANCH_MIN_VISITS: u32 = 10_000;
@@ -17,6 +20,7 @@ pub struct Analysis {
     branches: Vec<(u16, MoveInfo)>,
     evals: Vec<f32>,
     marks: Vec<(u16, Mark)>,
+    multi_pv: usize,
 }
 
 impl Analysis {
@@ -34,6 +38,14 @@ impl Analysis {
         }
     }
 
+    /// Create branches for the given number of most visited moves,
+    /// instead of only for moves which are close to the best one.
+    #[must_use]
+    pub fn with_multi_pv(mut self, multi_pv: usize) -> Self {
+        self.multi_pv = multi_pv;
+        self
+    }
+
     pub fn add_setting<T: Display>(&mut self, name: &str, value: T) {
         writeln!(self.settings, "[{name} \"{value}\"]").unwrap();
     }
@@ -49,7 +61,7 @@ impl Analysis {
         self.evals.push(eval);
     }
 
-    pub fn update(&mut self, node: &Node, played_move: Move) {
+    pub fn update(&mut self, node: NodeRef, played_move: Move) {
         let debug_info = node.debug(MAX_BRANCH_LENGTH);
 
         let ply = self.start_ply + self.played_moves.len() as u16;
@@ -74,7 +86,7 @@ impl Analysis {
             }
         }
 
-        for info in debug_info.0 {
+        for (rank, info) in debug_info.0.into_iter().enumerate() {
             // Add info for played move.
             if info.mov == played_move {
                 self.add_move(played_move, info, eval);
@@ -82,7 +94,12 @@ impl Analysis {
             }
 
             // Create branches for candidate moves.
-            if info.visits as f32 > top_visits as f32 * CANDIDATE_MOVE_RATIO {
+            let candidate = if self.multi_pv > 1 {
+                rank < self.multi_pv && info.visits > 0
+            } else {
+                info.visits as f32 > top_visits as f32 * CANDIDATE_MOVE_RATIO
+            };
+            if candidate {
                 self.branches.push((ply, info));
             }
         }
@@ -106,11 +123,13 @@ impl Display for Analysis {
         let mut ply = self.start_ply;
 
         // Helper closures.
-        let write_eval = |out: &mut String, eval, flip| {
+        let write_eval = |out: &mut String, eval: &f32, flip| {
+            let eval = if flip { -eval } else { *eval };
             write!(
                 out,
-                "{{evaluation: {:+.3}}}",
-                eval * if flip { -1.0 } else { 1.0 }
+                "{{evaluation: {:+.3}, win probability: {:.1}%}}",
+                eval,
+                100.0 * win_probability(eval)
             )
             .unwrap();
         };
@@ -266,7 +285,9 @@ mod tests {
                 mov: "Se4".parse().unwrap(),
                 visits: 0,
                 reward: -1.0,
+                draw: 0.0,
                 policy: 1.0,
+                proof: Default::default(),
                 continuation: Default::default(),
             },
             0.0,
@@ -21,15 +21,79 @@ impl<const N: usize> IncompleteExample<N> {
             game: self.game,
             policy: self.policy,
             result,
+            auxiliary: None,
         }
     }
 }
 
+/// Complete the examples of a game which was played until the end,
+/// recording the auxiliary targets as well as the result.
+/// The examples should be in the order they were played.
+pub fn complete_game<const N: usize>(examples: Vec<IncompleteExample<N>>, end: &Game<N>) -> Vec<Example<N>> {
+    let (white_result, road) = match end.result() {
+        GameResult::Winner { color, road } => (if color == Color::White { 1. } else { -1. }, road),
+        GameResult::Draw { .. } => (0., false),
+        GameResult::Ongoing => panic!("cannot complete examples with ongoing game"),
+    };
+    // The reply policy is only known if the next position was searched.
+    let replies: Vec<_> = examples
+        .iter()
+        .skip(1)
+        .map(|next| (next.game.ply, next.policy.clone()))
+        .chain(std::iter::once((0, Vec::new())))
+        .collect();
+    examples
+        .into_iter()
+        .zip(replies)
+        .map(|(ex, (next_ply, reply_policy))| {
+            let sign = if ex.game.to_move == Color::White { 1. } else { -1. };
+            let auxiliary = AuxiliaryTargets {
+                road,
+                flat_difference: sign * end.board.flat_diff() as f32,
+                plies_remaining: end.ply - ex.game.ply,
+                reply_policy: if next_ply == ex.game.ply + 1 {
+                    reply_policy
+                } else {
+                    Vec::new()
+                },
+            };
+            Example {
+                auxiliary: Some(auxiliary),
+                ..ex.complete(sign * white_result)
+            }
+        })
+        .collect()
+}
+
+/// Extra training targets which describe how the game continued.
+/// They are from the perspective of the player to move in the example.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AuxiliaryTargets {
+    /// Whether the game ended with a road (instead of flats or a draw).
+    pub road: bool,
+    /// Flat difference on the final board, without komi.
+    pub flat_difference: f32,
+    pub plies_remaining: u16,
+    /// Search policy of the opponent in the next position,
+    /// or empty if that position was not recorded.
+    pub reply_policy: Vec<(Move, u32)>,
+}
+
+// Scale auxiliary targets to a range similar to the game result.
+const FLAT_DIFFERENCE_SCALE: f32 = 10.0;
+const PLIES_REMAINING_SCALE: f32 = 100.0;
+
+/// Scale a predicted flat difference from the auxiliary head back to flats.
+pub(crate) fn unscale_flat_difference(prediction: f32) -> f32 {
+    prediction * FLAT_DIFFERENCE_SCALE
+}
+
 #[derive(Debug)]
 pub struct Example<const N: usize> {
     pub game: Game<N>,
     pub policy: Vec<(Move, u32)>,
     pub result: f32,
+    pub auxiliary: Option<AuxiliaryTargets>,
 }
 
 impl<const N: usize> Example<N> {
@@ -59,14 +123,21 @@ impl<const N: usize> Example<N> {
         }
     }
 
-    pub fn to_tensors(&self) -> Vec<(Tensor, Tensor, f32)> {
+    /// Get the policy target for each symmetry.
+    /// An empty policy gives all zeros.
+    fn symmetric_policies(policy: &[(Move, u32)]) -> [Vec<f32>; 8] {
         let mut pi = Self::empty_pi();
-        let total = self.policy.iter().map(|(_, c)| c).sum::<u32>() as f32;
-        for (m, value) in &self.policy {
+        let total = policy.iter().map(|(_, c)| c).sum::<u32>() as f32;
+        for (m, value) in policy {
             for (i, symm) in Symmetry::<N>::symmetries(*m).into_iter().enumerate() {
                 pi[i][move_index(&symm, N)] = *value as f32 / total;
             }
         }
+        pi
+    }
+
+    pub fn to_tensors(&self) -> Vec<(Tensor, Tensor, f32)> {
+        let pi = Self::symmetric_policies(&self.policy);
 
         self.game
             .clone()
@@ -76,6 +147,50 @@ impl<const N: usize> Example<N> {
             .map(|(i, game)| (game_repr(&game), Tensor::of_slice(&pi[i]), self.result))
             .collect()
     }
+
+    /// Get the auxiliary targets for each symmetry, in the same order as
+    /// [`Example::to_tensors`]. The first tensor holds the road, flat
+    /// difference and plies remaining targets, the second one holds the
+    /// reply policy, and the last value is the weight of the targets.
+    /// Examples without auxiliary targets get zeros with a weight of zero.
+    pub fn auxiliary_tensors(&self) -> Vec<(Tensor, Tensor, f32)> {
+        let (outcome, reply, weight) = match &self.auxiliary {
+            Some(auxiliary) => (
+                [
+                    if auxiliary.road { 1.0 } else { 0.0 },
+                    auxiliary.flat_difference / FLAT_DIFFERENCE_SCALE,
+                    auxiliary.plies_remaining as f32 / PLIES_REMAINING_SCALE,
+                ],
+                Self::symmetric_policies(&auxiliary.reply_policy),
+                1.0,
+            ),
+            None => ([0.0; 3], Self::empty_pi(), 0.0),
+        };
+        reply
+            .iter()
+            .map(|pi| (Tensor::of_slice(&outcome), Tensor::of_slice(pi), weight))
+            .collect()
+    }
+}
+
+fn format_policy(policy: &[(Move, u32)]) -> String {
+    policy
+        .iter()
+        .map(|(mov, visits)| format!("{mov}:{visits}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn parse_policy(s: &str) -> Result<Vec<(Move, u32)>, Box<dyn Error>> {
+    fn parse_pair(pair: &str) -> Result<(Move, u32), Box<dyn Error>> {
+        let (move_str, visit_str) = pair.split_once(':').ok_or("pair has missing delimiter")?;
+        Ok((move_str.parse()?, visit_str.parse()?))
+    }
+
+    if s.is_empty() {
+        return Ok(Vec::new());
+    }
+    s.split(',').map(parse_pair).collect()
 }
 
 impl<const N: usize> Display for Example<N> {
@@ -90,12 +205,20 @@ impl<const N: usize> Display for Example<N> {
             self.game.black_caps,
             self.game.half_komi,
             self.result,
-            self.policy
-                .iter()
-                .map(|(mov, visits)| format!("{mov}:{visits}"))
-                .collect::<Vec<_>>()
-                .join(",")
-        )
+            format_policy(&self.policy)
+        )?;
+        // Auxiliary targets are optional trailing fields.
+        if let Some(auxiliary) = &self.auxiliary {
+            write!(
+                f,
+                ";{};{};{};{}",
+                auxiliary.road,
+                auxiliary.flat_difference,
+                auxiliary.plies_remaining,
+                format_policy(&auxiliary.reply_policy)
+            )?;
+        }
+        Ok(())
     }
 }
 
@@ -115,19 +238,83 @@ impl<const N: usize> FromStr for Example<N> {
         game.half_komi = iter.next().ok_or("missing half komi")?.parse()?;
 
         let result = iter.next().ok_or("missing result")?.parse()?;
+        let policy = parse_policy(iter.next().ok_or("missing policy")?)?;
+
+        let auxiliary = match iter.next() {
+            Some(road) => Some(AuxiliaryTargets {
+                road: road.parse()?,
+                flat_difference: iter.next().ok_or("missing flat difference")?.parse()?,
+                plies_remaining: iter.next().ok_or("missing plies remaining")?.parse()?,
+                reply_policy: parse_policy(iter.next().ok_or("missing reply policy")?)?,
+            }),
+            None => None,
+        };
+
+        Ok(Example {
+            game,
+            result,
+            policy,
+            auxiliary,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tak::*;
 
-        fn parse_pair(pair: &str) -> Result<(Move, u32), Box<dyn Error>> {
-            let (move_str, visit_str) = pair.split_once(':').ok_or("pair has missing delimiter")?;
-            Ok((move_str.parse()?, visit_str.parse()?))
+    use super::{complete_game, Example, IncompleteExample};
+
+    #[test]
+    fn auxiliary_targets_round_trip() {
+        let moves = [
+            "a1", "e1", "b1", "c1", "d1", "c2", "e5", "c3", "e4", "c4", "e3", "c5",
+        ];
+        let mut game = Game::<5>::default();
+        let mut examples = Vec::new();
+        for mov in moves {
+            let mov: Move = mov.parse().unwrap();
+            // Skip recording one position, so that its parent has no reply policy.
+            if game.ply != 5 {
+                examples.push(IncompleteExample {
+                    game: game.clone(),
+                    policy: vec![(mov, 1)],
+                });
+            }
+            game.play(mov).unwrap();
         }
+        assert_eq!(game.result(), GameResult::Winner {
+            color: Color::Black,
+            road: true
+        });
 
-        let policy = iter
-            .next()
-            .ok_or("missing policy")?
-            .split(',')
-            .map(parse_pair)
-            .collect::<Result<Vec<_>, _>>()?;
+        let examples = complete_game(examples, &game);
+        let first = examples[0].auxiliary.as_ref().unwrap();
+        assert!(first.road);
+        assert_eq!(first.plies_remaining, 12);
+        assert_eq!(first.reply_policy, vec![("e1".parse().unwrap(), 1)]);
+        assert_eq!(examples[0].result, -1.0);
+        assert!(examples[4].auxiliary.as_ref().unwrap().reply_policy.is_empty());
+        assert!(examples
+            .last()
+            .unwrap()
+            .auxiliary
+            .as_ref()
+            .unwrap()
+            .reply_policy
+            .is_empty());
+
+        for example in examples {
+            let line = example.to_string();
+            let parsed: Example<5> = line.parse().unwrap();
+            assert_eq!(parsed.auxiliary, example.auxiliary);
+            assert_eq!(parsed.to_string(), line);
+        }
 
-        Ok(Example { game, result, policy })
+        // Examples without auxiliary targets keep the old format.
+        let line = "x5/x5/x5/x5/x5 1 1;21;1;21;1;4;1;a1:1";
+        let parsed: Example<5> = line.parse().unwrap();
+        assert!(parsed.auxiliary.is_none());
+        assert_eq!(parsed.to_string(), line);
     }
 }
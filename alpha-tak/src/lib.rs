@@ -11,12 +11,45 @@ mod model;
 mod player;
 mod repr;
 mod search;
+mod time_manager;
 
 pub use analysis::Analysis;
-pub use example::{Example, IncompleteExample};
-pub use model::{net5::Net5, net6::Net6, network::Network};
+pub use example::{complete_game, AuxiliaryTargets, Example, IncompleteExample};
+pub use model::{
+    cache::{CacheStats, CachedNetwork},
+    inference::{inference_channel, InferenceClient, InferenceServer, PendingEval},
+    net5::Net5,
+    net6::Net6,
+    network::{AuxiliaryOutput, Eval, Evaluator, Heads, Network},
+    score::{centiflats, eval_from_centiflats, win_probability, DEFAULT_LOGIT_PER_FLAT, MAX_CENTIFLATS},
+};
 pub use player::Player;
-pub use search::{MoveInfo, Node, NodeDebugInfo};
+pub use search::{
+    evaluate,
+    forced_win,
+    move_from_index,
+    move_index,
+    position_key,
+    Arena,
+    EdgeRef,
+    Fpu,
+    GumbelRoot,
+    MoveFilter,
+    MoveInfo,
+    Node,
+    NodeDebugInfo,
+    NodeId,
+    NodeRef,
+    PositionKey,
+    Proof,
+    PvEntry,
+    RootSelection,
+    SearchConfig,
+    SearchReport,
+    SearchResult,
+    Tree,
+};
+pub use time_manager::TimeManager;
 
 lazy_static! {
     static ref DEVICE: Device = Device::cuda_if_available();
@@ -0,0 +1,271 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+        Mutex,
+    },
+};
+
+use tak::*;
+use tch::{nn::VarStore, TchError, Tensor};
+
+use super::network::{AuxiliaryOutput, Eval, Heads, Network, Policy};
+use crate::{
+    example::Example,
+    search::{position_key, CompactOutput, PositionKey},
+};
+
+const DEFAULT_CAPACITY: usize = 100_000;
+
+#[derive(Debug, Default)]
+struct LruCache {
+    capacity: usize,
+    // Maps the hash of a position to its check, output and the tick it was
+    // last used.
+    entries: HashMap<u64, (u64, CompactOutput, u64)>,
+    // Maps tick of last use to position hash, oldest first.
+    order: BTreeMap<u64, u64>,
+    tick: u64,
+}
+
+impl LruCache {
+    fn get(&mut self, key: PositionKey) -> Option<(Policy, Eval)> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (check, output, last_used) = self.entries.get_mut(&key.hash)?;
+        // A different check means the hash collided with another position.
+        if *check != key.check {
+            return None;
+        }
+        self.order.remove(last_used);
+        self.order.insert(tick, key.hash);
+        *last_used = tick;
+        Some(output.output())
+    }
+
+    fn insert(&mut self, key: PositionKey, output: CompactOutput) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        let entry = (key.check, output, self.tick);
+        if let Some((_check, _output, last_used)) = self.entries.insert(key.hash, entry) {
+            self.order.remove(&last_used);
+        }
+        self.order.insert(self.tick, key.hash);
+
+        // Evict the least recently used entries.
+        while self.entries.len() > self.capacity {
+            let (&tick, &oldest) = self.order.iter().next().unwrap();
+            self.order.remove(&tick);
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Hit and miss counts of a [`CachedNetwork`], readable from other threads.
+#[derive(Clone, Debug, Default)]
+pub struct CacheStats {
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+impl CacheStats {
+    /// Get the number of cache hits and misses so far.
+    pub fn get(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Fraction of positions which were found in the cache.
+    pub fn hit_rate(&self) -> f64 {
+        let (hits, misses) = self.get();
+        if hits + misses == 0 {
+            0.0
+        } else {
+            hits as f64 / (hits + misses) as f64
+        }
+    }
+}
+
+/// Wraps a network and memoizes its outputs per position,
+/// evicting the least recently used positions once the capacity is reached.
+/// Positions which appear several times in one batch, such as transpositions
+/// reached by different move orders, are only evaluated once.
+#[derive(Debug)]
+pub struct CachedNetwork<NET> {
+    network: NET,
+    cache: Mutex<LruCache>,
+    stats: CacheStats,
+}
+
+impl<NET> CachedNetwork<NET> {
+    pub fn new(network: NET, capacity: usize) -> Self {
+        CachedNetwork {
+            network,
+            cache: Mutex::new(LruCache {
+                capacity,
+                ..Default::default()
+            }),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Get the wrapped network.
+    pub fn inner(&self) -> &NET {
+        &self.network
+    }
+
+    /// Get the number of cache hits and misses so far.
+    pub fn stats(&self) -> (u64, u64) {
+        self.stats.get()
+    }
+
+    /// Get a handle to the statistics, for reading them from other threads.
+    pub fn stats_handle(&self) -> CacheStats {
+        self.stats.clone()
+    }
+
+    /// Maximum number of cached outputs.
+    pub fn capacity(&self) -> usize {
+        self.cache.lock().unwrap().capacity
+    }
+
+    /// Number of cached outputs.
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Fraction of positions which were found in the cache.
+    pub fn hit_rate(&self) -> f64 {
+        self.stats.hit_rate()
+    }
+
+    /// Remove all cached outputs.
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+}
+
+impl<NET: Default> Default for CachedNetwork<NET> {
+    fn default() -> Self {
+        CachedNetwork::new(NET::default(), DEFAULT_CAPACITY)
+    }
+}
+
+impl<const N: usize, NET: Network<N>> Network<N> for CachedNetwork<NET> {
+    fn with_heads(heads: Heads) -> Self {
+        CachedNetwork::new(NET::with_heads(heads), DEFAULT_CAPACITY)
+    }
+
+    fn heads(&self) -> Heads {
+        self.network.heads()
+    }
+
+    fn vs(&self) -> &VarStore {
+        self.network.vs()
+    }
+
+    fn save<T: AsRef<Path>>(&self, path: T) -> Result<(), TchError> {
+        self.network.save(path)
+    }
+
+    fn load<T: AsRef<Path>>(path: T) -> Result<Self, TchError> {
+        Ok(CachedNetwork::new(NET::load(path)?, DEFAULT_CAPACITY))
+    }
+
+    fn forward_mcts(&self, input: Tensor) -> (Tensor, Tensor) {
+        self.network.forward_mcts(input)
+    }
+
+    fn forward_training(&self, input: Tensor) -> (Tensor, Tensor) {
+        self.network.forward_training(input)
+    }
+
+    fn forward_training_auxiliary(&self, input: Tensor) -> (Tensor, Tensor, Option<AuxiliaryOutput>) {
+        self.network.forward_training_auxiliary(input)
+    }
+
+    fn policy_eval(&self, games: &[Game<N>]) -> Vec<(Policy, Eval)> {
+        let keys: Vec<_> = games.iter().map(position_key).collect();
+
+        // Look up cached outputs and collect the remaining unique positions.
+        let mut outputs = Vec::with_capacity(games.len());
+        let mut pending = HashMap::new();
+        let mut to_eval = Vec::new();
+        {
+            let mut cache = self.cache.lock().unwrap();
+            for (game, &key) in games.iter().zip(&keys) {
+                let output = cache.get(key);
+                if output.is_none() && !pending.contains_key(&key) {
+                    pending.insert(key, to_eval.len());
+                    to_eval.push(game.clone());
+                }
+                outputs.push(output);
+            }
+        }
+        self.stats
+            .hits
+            .fetch_add((games.len() - to_eval.len()) as u64, Ordering::Relaxed);
+        self.stats
+            .misses
+            .fetch_add(to_eval.len() as u64, Ordering::Relaxed);
+
+        // Do not hold the lock while the network is running.
+        let evaluated = self.network.policy_eval(&to_eval);
+
+        let mut cache = self.cache.lock().unwrap();
+        for (&key, &i) in &pending {
+            cache.insert(key, CompactOutput::new(&to_eval[i], &evaluated[i]));
+        }
+        outputs
+            .into_iter()
+            .zip(keys)
+            .map(|(output, key)| output.unwrap_or_else(|| evaluated[pending[&key]].clone()))
+            .collect()
+    }
+
+    /// Predictions of the flat difference are not cached.
+    fn flat_difference(&self, games: &[Game<N>]) -> Option<Vec<f32>> {
+        self.network.flat_difference(games)
+    }
+
+    /// Train the wrapped network. Cached outputs are discarded
+    /// since they no longer match the network.
+    fn train(&mut self, examples: &[Example<N>]) {
+        self.network.train(examples);
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LruCache;
+    use crate::search::{CompactOutput, PositionKey};
+
+    #[test]
+    fn colliding_hashes_are_not_shared() {
+        let game = tak::Game::<5>::default();
+        let output = CompactOutput::new(&game, &(vec![1.0; crate::repr::output_size(5)], 0.5.into()));
+        let mut cache = LruCache {
+            capacity: 10,
+            ..Default::default()
+        };
+        cache.insert(PositionKey { hash: 1, check: 2 }, output);
+        assert!(cache.get(PositionKey { hash: 1, check: 2 }).is_some());
+        assert!(cache.get(PositionKey { hash: 1, check: 3 }).is_none());
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use tak::*;
+
+use super::network::{Eval, Evaluator, Network, Policy};
+
+#[derive(Debug)]
+enum Request<const N: usize> {
+    Evaluate(Vec<Game<N>>, Sender<Vec<(Policy, Eval)>>),
+    /// Flat difference predictions are rare, so they skip batching.
+    FlatDifference(Vec<Game<N>>, Sender<Option<Vec<f32>>>),
+}
+
+/// Create an inference server together with a client for it.
+/// More clients can be made by cloning the first one.
+/// Requests are gathered until the batch holds `max_batch` positions
+/// or `max_latency` has passed since the first request of the batch.
+pub fn inference_channel<const N: usize>(
+    max_batch: usize,
+    max_latency: Duration,
+) -> (InferenceServer<N>, InferenceClient<N>) {
+    let (request_tx, request_rx) = channel();
+    (
+        InferenceServer {
+            request_rx,
+            max_batch,
+            max_latency,
+            batches: 0,
+            positions: 0,
+        },
+        InferenceClient { request_tx },
+    )
+}
+
+/// Evaluates positions from any number of searches in dynamic batches.
+/// The server runs on the thread which owns the network.
+#[derive(Debug)]
+pub struct InferenceServer<const N: usize> {
+    request_rx: Receiver<Request<N>>,
+    max_batch: usize,
+    max_latency: Duration,
+    batches: u64,
+    positions: u64,
+}
+
+impl<const N: usize> InferenceServer<N> {
+    /// Answer requests until all clients have been dropped.
+    pub fn serve<NET: Network<N>>(&mut self, network: &NET) {
+        while let Ok(first) = self.request_rx.recv() {
+            self.serve_batch(network, first);
+        }
+    }
+
+    /// Answer the requests which arrive within the timeout, if any.
+    /// This allows one thread to serve several networks.
+    /// Returns `false` once all clients have been dropped.
+    pub fn serve_for<NET: Network<N>>(&mut self, network: &NET, timeout: Duration) -> bool {
+        match self.request_rx.recv_timeout(timeout) {
+            Ok(first) => {
+                self.serve_batch(network, first);
+                true
+            }
+            Err(RecvTimeoutError::Timeout) => true,
+            Err(RecvTimeoutError::Disconnected) => false,
+        }
+    }
+
+    fn serve_batch<NET: Network<N>>(&mut self, network: &NET, first: Request<N>) {
+        let deadline = Instant::now() + self.max_latency;
+        let mut positions = 0;
+        let mut requests = Vec::new();
+        let mut next = Some(first);
+        while let Some(request) = next.take() {
+            match request {
+                Request::Evaluate(games, reply_tx) => {
+                    positions += games.len();
+                    requests.push((games, reply_tx));
+                }
+                Request::FlatDifference(games, reply_tx) => {
+                    reply_tx.send(network.flat_difference(&games)).ok();
+                }
+            }
+            if positions < self.max_batch {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                next = self.request_rx.recv_timeout(timeout).ok();
+            }
+        }
+        if requests.is_empty() {
+            return;
+        }
+
+        let games: Vec<_> = requests
+            .iter()
+            .flat_map(|(games, _)| games.iter().cloned())
+            .collect();
+        let mut outputs = network.policy_eval(&games).into_iter();
+        for (games, reply_tx) in requests {
+            // The client might have given up on the result.
+            reply_tx.send(outputs.by_ref().take(games.len()).collect()).ok();
+        }
+
+        self.batches += 1;
+        self.positions += positions as u64;
+    }
+
+    /// Average number of positions evaluated per network call.
+    pub fn average_batch_size(&self) -> f64 {
+        if self.batches == 0 {
+            0.0
+        } else {
+            self.positions as f64 / self.batches as f64
+        }
+    }
+}
+
+/// Handle for sending positions to an [`InferenceServer`].
+#[derive(Clone, Debug)]
+pub struct InferenceClient<const N: usize> {
+    request_tx: Sender<Request<N>>,
+}
+
+impl<const N: usize> InferenceClient<N> {
+    /// Send positions for evaluation without waiting for the result.
+    pub fn request(&self, games: Vec<Game<N>>) -> PendingEval {
+        let (reply_tx, reply_rx) = channel();
+        self.request_tx
+            .send(Request::Evaluate(games, reply_tx))
+            .expect("inference server has stopped");
+        PendingEval(reply_rx)
+    }
+
+    /// Predict the flat difference with the network of the server.
+    /// See [`Network::flat_difference`].
+    pub fn flat_difference(&self, games: &[Game<N>]) -> Option<Vec<f32>> {
+        let (reply_tx, reply_rx) = channel();
+        self.request_tx
+            .send(Request::FlatDifference(games.to_vec(), reply_tx))
+            .expect("inference server has stopped");
+        reply_rx.recv().expect("inference server has stopped")
+    }
+}
+
+impl<const N: usize> Evaluator<N> for InferenceClient<N> {
+    fn evaluate_batch(&self, games: &[Game<N>]) -> Vec<(Policy, Eval)> {
+        if games.is_empty() {
+            return Vec::new();
+        }
+        self.request(games.to_vec()).wait()
+    }
+}
+
+/// Result of a request which is still being evaluated.
+#[derive(Debug)]
+pub struct PendingEval(Receiver<Vec<(Policy, Eval)>>);
+
+impl PendingEval {
+    /// Block until the result is ready.
+    pub fn wait(self) -> Vec<(Policy, Eval)> {
+        self.0.recv().expect("inference server has stopped")
+    }
+
+    /// Get the result if it is ready.
+    pub fn try_get(&self) -> Option<Vec<(Policy, Eval)>> {
+        match self.0.try_recv() {
+            Ok(outputs) => Some(outputs),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!("inference server has stopped"),
+        }
+    }
+}
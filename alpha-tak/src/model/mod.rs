@@ -1,4 +1,7 @@
+pub mod cache;
+pub mod inference;
 pub mod net5;
 pub mod net6;
 pub mod network;
 mod res_block;
+pub mod score;
@@ -5,10 +5,11 @@ use tak::*;
 use tch::{nn, Kind, Tensor};
 
 use super::{
-    network::{Eval, Network, Policy},
+    network::{evals_from_tensor, value_activation, AuxiliaryOutput, Eval, Heads, Network, Policy},
     res_block::ResBlock,
 };
 use crate::{
+    example::unscale_flat_difference,
     repr::{game_repr, input_channels, possible_moves},
     DEVICE,
 };
@@ -24,10 +25,19 @@ pub struct Net5 {
     residual_blocks: ArrayVec<ResBlock, RES_BLOCKS>,
     fully_connected_policy: nn::Linear,
     fully_connected_eval: nn::Linear,
+    /// Outcome and reply policy heads.
+    auxiliary: Option<(nn::Linear, nn::Linear)>,
+    heads: Heads,
 }
 
 impl Default for Net5 {
     fn default() -> Self {
+        Self::new(Heads::default())
+    }
+}
+
+impl Net5 {
+    fn new(heads: Heads) -> Self {
         let vs = nn::VarStore::new(*DEVICE);
         let root = &vs.root();
 
@@ -59,7 +69,20 @@ impl Default for Net5 {
             possible_moves(5) as i64,
             Default::default(),
         );
-        let fully_connected_eval = nn::linear(root, FILTERS * 5 * 5, 1, Default::default());
+        // The W/D/L head has its own name, so that a network
+        // with a scalar value head can never be loaded as one with a W/D/L head.
+        let fully_connected_eval = if heads.wdl {
+            nn::linear(root / "wdl", FILTERS * 5 * 5, 3, Default::default())
+        } else {
+            nn::linear(root, FILTERS * 5 * 5, 1, Default::default())
+        };
+        let auxiliary = heads.auxiliary.then(|| {
+            let aux = &(root / "aux");
+            (
+                nn::linear(aux, FILTERS * 5 * 5, 3, Default::default()),
+                nn::linear(aux, FILTERS * 5 * 5, possible_moves(5) as i64, Default::default()),
+            )
+        });
 
         Net5 {
             vs,
@@ -68,11 +91,11 @@ impl Default for Net5 {
             residual_blocks,
             fully_connected_policy,
             fully_connected_eval,
+            auxiliary,
+            heads,
         }
     }
-}
 
-impl Net5 {
     fn forward_conv(&self, input: Tensor, train: bool) -> Tensor {
         self.residual_blocks
             .iter()
@@ -88,6 +111,14 @@ impl Net5 {
 }
 
 impl Network<5> for Net5 {
+    fn with_heads(heads: Heads) -> Self {
+        Self::new(heads)
+    }
+
+    fn heads(&self) -> Heads {
+        self.heads
+    }
+
     fn vs(&self) -> &nn::VarStore {
         &self.vs
     }
@@ -98,23 +129,38 @@ impl Network<5> for Net5 {
     }
 
     fn load<T: AsRef<Path>>(path: T) -> Result<Self, tch::TchError> {
-        let mut nn = Self::default();
-        nn.vs.load(path)?;
-        Ok(nn)
+        let mut error = None;
+        for heads in Heads::ALL {
+            let mut nn = Self::new(heads);
+            match nn.vs.load(&path) {
+                Ok(()) => return Ok(nn),
+                Err(err) => error = Some(err),
+            }
+        }
+        Err(error.expect("no heads were tried"))
     }
 
     fn forward_mcts(&self, input: Tensor) -> (Tensor, Tensor) {
         let s = self.forward_conv(input, false);
         let policy = s.apply(&self.fully_connected_policy).softmax(1, Kind::Float);
-        let eval = s.apply(&self.fully_connected_eval).tanh_();
+        let eval = value_activation(s.apply(&self.fully_connected_eval), self.heads.wdl, false);
         (policy, eval)
     }
 
     fn forward_training(&self, input: Tensor) -> (Tensor, Tensor) {
+        let (policy, eval, _auxiliary) = self.forward_training_auxiliary(input);
+        (policy, eval)
+    }
+
+    fn forward_training_auxiliary(&self, input: Tensor) -> (Tensor, Tensor, Option<AuxiliaryOutput>) {
         let s = self.forward_conv(input, true);
         let policy = s.apply(&self.fully_connected_policy).log_softmax(1, Kind::Float);
-        let eval = s.apply(&self.fully_connected_eval).tanh_();
-        (policy, eval)
+        let eval = value_activation(s.apply(&self.fully_connected_eval), self.heads.wdl, true);
+        let auxiliary = self.auxiliary.as_ref().map(|(outcome, reply)| AuxiliaryOutput {
+            outcome: s.apply(outcome),
+            reply_policy: s.apply(reply).log_softmax(1, Kind::Float),
+        });
+        (policy, eval, auxiliary)
     }
 
     fn policy_eval(&self, games: &[Game<5>]) -> Vec<(Policy, Eval)> {
@@ -125,7 +171,22 @@ impl Network<5> for Net5 {
         let input = Tensor::stack(&game_tensors, 0).to_device_(*DEVICE, Kind::Float, true, false);
         let (policy, eval) = self.forward_mcts(input);
         let policies: Vec<Vec<f32>> = policy.into();
-        let evals: Vec<f32> = eval.into();
+        let evals = evals_from_tensor(eval, self.heads.wdl);
         policies.into_iter().zip(evals.into_iter()).collect()
     }
+
+    fn flat_difference(&self, games: &[Game<5>]) -> Option<Vec<f32>> {
+        let (outcome, _reply) = self.auxiliary.as_ref()?;
+        if games.is_empty() {
+            return Some(Vec::new());
+        }
+        let game_tensors: Vec<_> = games.iter().map(game_repr).collect();
+        let input = Tensor::stack(&game_tensors, 0).to_device_(*DEVICE, Kind::Float, true, false);
+        let flats: Vec<f32> = self
+            .forward_conv(input, false)
+            .apply(outcome)
+            .narrow(1, 1, 1)
+            .into();
+        Some(flats.into_iter().map(unscale_flat_difference).collect())
+    }
 }
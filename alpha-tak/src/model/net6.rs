@@ -5,10 +5,11 @@ use tak::*;
 use tch::{nn, Kind, TchError, Tensor};
 
 use super::{
-    network::{Eval, Network, Policy},
+    network::{evals_from_tensor, value_activation, AuxiliaryOutput, Eval, Heads, Network, Policy},
     res_block::ResBlock,
 };
 use crate::{
+    example::unscale_flat_difference,
     repr::{game_repr, input_channels, move_channels, output_size},
     DEVICE,
 };
@@ -24,10 +25,19 @@ pub struct Net6 {
     residual_blocks: ArrayVec<ResBlock, RES_BLOCKS>,
     final_conv_policy: nn::Conv2D,
     fully_connected_eval: nn::Linear,
+    /// Outcome and reply policy heads.
+    auxiliary: Option<(nn::Linear, nn::Conv2D)>,
+    heads: Heads,
 }
 
 impl Default for Net6 {
     fn default() -> Self {
+        Self::new(Heads::default())
+    }
+}
+
+impl Net6 {
+    fn new(heads: Heads) -> Self {
         let vs = nn::VarStore::new(*DEVICE);
         let root = &vs.root();
 
@@ -54,7 +64,20 @@ impl Default for Net6 {
         }
 
         let final_conv_policy = nn::conv2d(root, FILTERS, move_channels(6) as i64, 3, conv_config);
-        let fully_connected_eval = nn::linear(root, FILTERS * 6 * 6, 1, Default::default());
+        // The W/D/L head has its own name, so that a network
+        // with a scalar value head can never be loaded as one with a W/D/L head.
+        let fully_connected_eval = if heads.wdl {
+            nn::linear(root / "wdl", FILTERS * 6 * 6, 3, Default::default())
+        } else {
+            nn::linear(root, FILTERS * 6 * 6, 1, Default::default())
+        };
+        let auxiliary = heads.auxiliary.then(|| {
+            let aux = &(root / "aux");
+            (
+                nn::linear(aux, FILTERS * 6 * 6, 3, Default::default()),
+                nn::conv2d(aux, FILTERS, move_channels(6) as i64, 3, conv_config),
+            )
+        });
 
         Net6 {
             vs,
@@ -63,11 +86,11 @@ impl Default for Net6 {
             residual_blocks,
             final_conv_policy,
             fully_connected_eval,
+            auxiliary,
+            heads,
         }
     }
-}
 
-impl Net6 {
     fn forward_conv(&self, input: Tensor, train: bool) -> Tensor {
         self.residual_blocks.iter().fold(
             input
@@ -80,6 +103,14 @@ impl Net6 {
 }
 
 impl Network<6> for Net6 {
+    fn with_heads(heads: Heads) -> Self {
+        Self::new(heads)
+    }
+
+    fn heads(&self) -> Heads {
+        self.heads
+    }
+
     fn vs(&self) -> &nn::VarStore {
         &self.vs
     }
@@ -90,9 +121,15 @@ impl Network<6> for Net6 {
     }
 
     fn load<T: AsRef<Path>>(path: T) -> Result<Self, TchError> {
-        let mut nn = Self::default();
-        nn.vs.load(path)?;
-        Ok(nn)
+        let mut error = None;
+        for heads in Heads::ALL {
+            let mut nn = Self::new(heads);
+            match nn.vs.load(&path) {
+                Ok(()) => return Ok(nn),
+                Err(err) => error = Some(err),
+            }
+        }
+        Err(error.expect("no heads were tried"))
     }
 
     fn forward_mcts(&self, input: Tensor) -> (Tensor, Tensor) {
@@ -103,12 +140,17 @@ impl Network<6> for Net6 {
             .softmax(1, Kind::Float);
         let eval = s
             .view([-1, FILTERS * 6 * 6])
-            .apply_t(&self.fully_connected_eval, false)
-            .tanh_();
+            .apply_t(&self.fully_connected_eval, false);
+        let eval = value_activation(eval, self.heads.wdl, false);
         (policy, eval)
     }
 
     fn forward_training(&self, input: Tensor) -> (Tensor, Tensor) {
+        let (policy, eval, _auxiliary) = self.forward_training_auxiliary(input);
+        (policy, eval)
+    }
+
+    fn forward_training_auxiliary(&self, input: Tensor) -> (Tensor, Tensor, Option<AuxiliaryOutput>) {
         let s = self.forward_conv(input, true);
         let policy = s
             .apply_t(&self.final_conv_policy, true)
@@ -116,9 +158,16 @@ impl Network<6> for Net6 {
             .log_softmax(1, Kind::Float);
         let eval = s
             .view([-1, FILTERS * 6 * 6])
-            .apply_t(&self.fully_connected_eval, true)
-            .tanh_();
-        (policy, eval)
+            .apply_t(&self.fully_connected_eval, true);
+        let eval = value_activation(eval, self.heads.wdl, true);
+        let auxiliary = self.auxiliary.as_ref().map(|(outcome, reply)| AuxiliaryOutput {
+            outcome: s.view([-1, FILTERS * 6 * 6]).apply(outcome),
+            reply_policy: s
+                .apply_t(reply, true)
+                .view([-1, output_size(6) as i64])
+                .log_softmax(1, Kind::Float),
+        });
+        (policy, eval, auxiliary)
     }
 
     fn policy_eval(&self, games: &[Game<6>]) -> Vec<(Policy, Eval)> {
@@ -129,11 +178,27 @@ impl Network<6> for Net6 {
         let input = Tensor::stack(&game_tensors, 0).to_device_(*DEVICE, Kind::Float, true, false);
         let (policy, eval) = self.forward_mcts(input);
         let policies: Vec<Vec<f32>> = policy.into();
-        let evals: Vec<f32> = eval.into();
+        let evals = evals_from_tensor(eval, self.heads.wdl);
 
         assert!(policies.iter().all(|pol| pol.len() == output_size(6))); // TODO remove
         assert!(evals.len() == policies.len() && evals.len() == games.len());
 
         policies.into_iter().zip(evals.into_iter()).collect()
     }
+
+    fn flat_difference(&self, games: &[Game<6>]) -> Option<Vec<f32>> {
+        let (outcome, _reply) = self.auxiliary.as_ref()?;
+        if games.is_empty() {
+            return Some(Vec::new());
+        }
+        let game_tensors: Vec<_> = games.iter().map(game_repr).collect();
+        let input = Tensor::stack(&game_tensors, 0).to_device_(*DEVICE, Kind::Float, true, false);
+        let flats: Vec<f32> = self
+            .forward_conv(input, false)
+            .view([-1, FILTERS * 6 * 6])
+            .apply(outcome)
+            .narrow(1, 1, 1)
+            .into();
+        Some(flats.into_iter().map(unscale_flat_difference).collect())
+    }
 }
@@ -19,11 +19,137 @@ const WEIGHT_DECAY: f64 = 1e-4;
 // Product of CHUNK_SIZE and CHUNKS_IN_STEP is the effective batch size.
 const CHUNK_SIZE: usize = 500;
 const CHUNKS_IN_STEP: usize = 20;
+// Weight of the auxiliary losses relative to the policy and value losses.
+const AUXILIARY_WEIGHT: f64 = 0.25;
 
 pub type Policy = Vec<f32>;
-pub type Eval = f32;
+
+/// Value of a position for the player to move.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Eval {
+    /// Expected score in `[-1, 1]`, which is the win minus the loss
+    /// probability.
+    pub score: f32,
+    /// Probability of a draw. Networks without a W/D/L head always predict
+    /// zero.
+    pub draw: f32,
+}
+
+impl Eval {
+    pub const DRAW: Eval = Eval {
+        score: 0.0,
+        draw: 1.0,
+    };
+    pub const LOSS: Eval = Eval {
+        score: -1.0,
+        draw: 0.0,
+    };
+    pub const WIN: Eval = Eval {
+        score: 1.0,
+        draw: 0.0,
+    };
+
+    pub fn from_wdl(win: f32, draw: f32, loss: f32) -> Self {
+        Eval {
+            score: win - loss,
+            draw,
+        }
+    }
+
+    /// Get the win, draw and loss probabilities.
+    pub fn wdl(self) -> (f32, f32, f32) {
+        let win = (1.0 + self.score - self.draw) / 2.0;
+        (win, self.draw, 1.0 - win - self.draw)
+    }
+
+    /// The same evaluation from the perspective of the other player.
+    #[must_use]
+    pub fn flip(self) -> Self {
+        Eval {
+            score: -self.score,
+            draw: self.draw,
+        }
+    }
+}
+
+impl From<f32> for Eval {
+    fn from(score: f32) -> Self {
+        Eval { score, draw: 0.0 }
+    }
+}
+
+/// Apply the activation of the value head.
+/// W/D/L heads output log-probabilities during training.
+pub(crate) fn value_activation(mut eval: Tensor, wdl: bool, train: bool) -> Tensor {
+    match (wdl, train) {
+        (true, true) => eval.log_softmax(1, Kind::Float),
+        (true, false) => eval.softmax(1, Kind::Float),
+        (false, _) => eval.tanh_(),
+    }
+}
+
+/// Convert the output of the value head into evaluations.
+pub(crate) fn evals_from_tensor(eval: Tensor, wdl: bool) -> Vec<Eval> {
+    if wdl {
+        let wdl: Vec<Vec<f32>> = eval.into();
+        wdl.into_iter()
+            .map(|wdl| Eval::from_wdl(wdl[0], wdl[1], wdl[2]))
+            .collect()
+    } else {
+        let evals: Vec<f32> = eval.into();
+        evals.into_iter().map(Eval::from).collect()
+    }
+}
+
+/// Optional heads of a network.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Heads {
+    /// The value head predicts win, draw and loss probabilities.
+    pub wdl: bool,
+    /// Heads which predict the auxiliary targets of examples.
+    pub auxiliary: bool,
+}
+
+impl Heads {
+    /// Every combination of heads, from the most to the fewest.
+    /// Loading ignores variables in the file which the network does not have,
+    /// so networks try to load with more heads first.
+    pub(crate) const ALL: [Heads; 4] = [
+        Heads {
+            wdl: true,
+            auxiliary: true,
+        },
+        Heads {
+            wdl: true,
+            auxiliary: false,
+        },
+        Heads {
+            wdl: false,
+            auxiliary: true,
+        },
+        Heads {
+            wdl: false,
+            auxiliary: false,
+        },
+    ];
+}
+
+/// Outputs of the auxiliary heads during training.
+#[derive(Debug)]
+pub struct AuxiliaryOutput {
+    /// Road logit, flat difference and plies remaining, scaled like the
+    /// targets.
+    pub outcome: Tensor,
+    /// Log-probabilities of the reply policy.
+    pub reply_policy: Tensor,
+}
 
 pub trait Network<const N: usize>: Default {
+    fn with_heads(heads: Heads) -> Self;
+    fn heads(&self) -> Heads {
+        Heads::default()
+    }
+
     fn vs(&self) -> &VarStore;
 
     fn save<T: AsRef<Path>>(&self, path: T) -> Result<(), TchError>;
@@ -31,8 +157,21 @@ pub trait Network<const N: usize>: Default {
 
     fn forward_mcts(&self, input: Tensor) -> (Tensor, Tensor);
     fn forward_training(&self, input: Tensor) -> (Tensor, Tensor);
+    /// Like `forward_training`, but also get the outputs of the auxiliary
+    /// heads.
+    fn forward_training_auxiliary(&self, input: Tensor) -> (Tensor, Tensor, Option<AuxiliaryOutput>) {
+        let (policy, eval) = self.forward_training(input);
+        (policy, eval, None)
+    }
     fn policy_eval(&self, games: &[Game<N>]) -> Vec<(Policy, Eval)>;
 
+    /// Predict the flat difference at the end of the game from the perspective
+    /// of the player to move. Only networks with a flat difference head support
+    /// this.
+    fn flat_difference(&self, _games: &[Game<N>]) -> Option<Vec<f32>> {
+        None
+    }
+
     /// Train the network on a set of examples.
     fn train(&mut self, examples: &[Example<N>]) {
         println!("starting training with {} examples", examples.len());
@@ -70,21 +209,39 @@ pub trait Network<const N: usize>: Default {
         // Get network output.
         let input = Tensor::stack(&inputs, 0).to_device_(*DEVICE, Kind::Float, true, false);
         let batch_size = input.size()[0];
-        let (policy, eval) = self.forward_training(input);
+        let (policy, eval, auxiliary) = self.forward_training_auxiliary(input);
 
         // Get the target.
         let p = Tensor::stack(&policies, 0)
             .to_device_(*DEVICE, Kind::Float, true, false)
             .view(policy.size().as_slice());
-        let z = Tensor::of_slice(&results)
-            .unsqueeze_(1)
-            .to_device_(*DEVICE, Kind::Float, true, false);
 
         // Calculate loss.
         let loss_p = -(p * policy).sum(Kind::Float) / batch_size;
-        let loss_z = (z - eval).square_().sum(Kind::Float) / batch_size;
+        let loss_z = if self.heads().wdl {
+            // Cross-entropy with the one-hot game outcome.
+            let one_hot = |b: bool| if b { 1.0 } else { 0.0 };
+            let wdl: Vec<f32> = results
+                .iter()
+                .flat_map(|&r| [one_hot(r > 0.0), one_hot(r == 0.0), one_hot(r < 0.0)])
+                .collect();
+            let z = Tensor::of_slice(&wdl)
+                .view([-1, 3])
+                .to_device_(*DEVICE, Kind::Float, true, false);
+            -(z * eval).sum(Kind::Float) / batch_size
+        } else {
+            let z = Tensor::of_slice(&results)
+                .unsqueeze_(1)
+                .to_device_(*DEVICE, Kind::Float, true, false);
+            (z - eval).square_().sum(Kind::Float) / batch_size
+        };
         println!("p={loss_p:?}\t z={loss_z:?}");
-        let total_loss = loss_z + loss_p;
+        let mut total_loss = loss_z + loss_p;
+        if let Some(auxiliary) = auxiliary {
+            let loss_a = auxiliary_loss(auxiliary, examples) / batch_size;
+            println!("a={loss_a:?}");
+            total_loss = total_loss + loss_a * AUXILIARY_WEIGHT;
+        }
 
         // Back-propagate loss.
         total_loss.backward();
@@ -96,3 +253,49 @@ pub trait Network<const N: usize>: Default {
         }
     }
 }
+
+/// Sum of the auxiliary losses over all symmetries of the examples.
+/// Examples without auxiliary targets do not contribute.
+fn auxiliary_loss<const N: usize>(output: AuxiliaryOutput, examples: &[&Example<N>]) -> Tensor {
+    let mut outcomes = Vec::new();
+    let mut replies = Vec::new();
+    let mut mask = Vec::new();
+    for (outcome, reply, weight) in examples.iter().flat_map(|ex| ex.auxiliary_tensors()) {
+        outcomes.push(outcome);
+        replies.push(reply);
+        mask.push(weight);
+    }
+    let outcome = Tensor::stack(&outcomes, 0).to_device_(*DEVICE, Kind::Float, true, false);
+    let reply = Tensor::stack(&replies, 0)
+        .to_device_(*DEVICE, Kind::Float, true, false)
+        .view(output.reply_policy.size().as_slice());
+    let mask = Tensor::of_slice(&mask)
+        .unsqueeze_(1)
+        .to_device_(*DEVICE, Kind::Float, true, false);
+
+    let target = |i| outcome.narrow(1, i, 1);
+    let prediction = |i| output.outcome.narrow(1, i, 1);
+    // Binary cross-entropy for whether the game ended with a road.
+    let road_logit = prediction(0);
+    let loss_road =
+        -(target(0) * road_logit.log_sigmoid() + (target(0) * -1.0 + 1.0) * (-road_logit).log_sigmoid());
+    let loss_flats = (prediction(1) - target(1)).square_();
+    let loss_plies = (prediction(2) - target(2)).square_();
+    // Missing reply policies are all zeros, so they do not need the mask.
+    let loss_reply = -(reply * output.reply_policy).sum(Kind::Float);
+
+    (mask * (loss_road + loss_flats + loss_plies)).sum(Kind::Float) + loss_reply
+}
+
+/// Anything that can evaluate positions for the search.
+/// This is implemented for every network, as well as for
+/// clients of an [`InferenceServer`](super::inference::InferenceServer).
+pub trait Evaluator<const N: usize> {
+    fn evaluate_batch(&self, games: &[Game<N>]) -> Vec<(Policy, Eval)>;
+}
+
+impl<const N: usize, NET: Network<N>> Evaluator<N> for NET {
+    fn evaluate_batch(&self, games: &[Game<N>]) -> Vec<(Policy, Eval)> {
+        self.policy_eval(games)
+    }
+}
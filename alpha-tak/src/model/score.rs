@@ -0,0 +1,54 @@
+//! Conversions of the value output of the network, which is in `[-1, 1]`,
+//! into numbers which are easier to interpret.
+
+/// How much one flat of advantage changes the logit of the win probability.
+/// This is a hand-picked guess (a single flat is worth about 62%) and not
+/// fitted to game results, so it can be changed with
+/// [`SearchConfig::logit_per_flat`](crate::SearchConfig::logit_per_flat)
+/// once a network has been calibrated.
+pub const DEFAULT_LOGIT_PER_FLAT: f32 = 0.5;
+
+/// Scores are clamped to this, which is reported for won positions.
+pub const MAX_CENTIFLATS: i32 = 10_000;
+
+/// Probability of winning, counting draws as half a win.
+pub fn win_probability(eval: f32) -> f32 {
+    (eval.clamp(-1.0, 1.0) + 1.0) / 2.0
+}
+
+/// Score in hundredths of a flat, like the centipawns of chess engines.
+pub fn centiflats(eval: f32, logit_per_flat: f32) -> i32 {
+    let p = win_probability(eval);
+    let logit = (p / (1.0 - p)).ln();
+    (100.0 * logit / logit_per_flat)
+        .round()
+        .clamp(-MAX_CENTIFLATS as f32, MAX_CENTIFLATS as f32) as i32
+}
+
+/// Inverse of [`centiflats`].
+pub fn eval_from_centiflats(centiflats: i32, logit_per_flat: f32) -> f32 {
+    let logit = centiflats as f32 / 100.0 * logit_per_flat;
+    (logit / 2.0).tanh()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_are_symmetric() {
+        let scale = DEFAULT_LOGIT_PER_FLAT;
+        assert_eq!(win_probability(0.0), 0.5);
+        assert_eq!(win_probability(1.0), 1.0);
+        assert_eq!(centiflats(0.0, scale), 0);
+        assert_eq!(centiflats(1.0, scale), MAX_CENTIFLATS);
+        assert_eq!(centiflats(-1.0, scale), -MAX_CENTIFLATS);
+        for eval in [-0.9, -0.3, 0.1, 0.5, 0.8] {
+            assert_eq!(centiflats(eval, scale), -centiflats(-eval, scale));
+            assert!((eval_from_centiflats(centiflats(eval, scale), scale) - eval).abs() < 1e-3);
+        }
+        assert_eq!(centiflats(eval_from_centiflats(100, scale), scale), 100);
+        // A larger scale means each flat is worth more, so the score shrinks.
+        assert_eq!(centiflats(0.5, 2.0 * scale), centiflats(0.5, scale) / 2);
+    }
+}
@@ -1,44 +1,58 @@
 use std::{
-    ops::DerefMut,
     sync::{
         mpsc::{channel, Receiver, Sender},
         Arc,
         Mutex,
+        RwLock,
     },
     thread::spawn,
+    time::Instant,
 };
 
 use tak::*;
 
 use crate::{
     analysis::Analysis,
-    example::{Example, IncompleteExample},
-    model::network::Network,
-    search::{Node, NodeDebugInfo},
+    example::{complete_game, Example, IncompleteExample},
+    model::network::Evaluator,
+    search::{GumbelRoot, MoveFilter, NodeDebugInfo, SearchConfig, SearchReport, Tree},
 };
 
-type Request<const N: usize> = (Game<N>, u32);
-type Response<const N: usize> = (Vec<Vec<usize>>, Vec<Game<N>>);
+const MAX_PV_LENGTH: usize = 20;
 
-pub struct Player<'a, const N: usize, NET: Network<N>> {
-    node: Arc<Mutex<Node>>,
+// Requests and responses carry the generation of the tree they were made
+// for, so that moves can be played while batches are in flight.
+type Request<const N: usize> = (Game<N>, u32, u32);
+type Response<const N: usize> = (u32, Vec<Vec<usize>>, Vec<Game<N>>);
+
+pub struct Player<'a, const N: usize, NET: Evaluator<N>> {
+    tree: Arc<RwLock<Tree>>,
+    gumbel: Arc<Mutex<Option<GumbelRoot>>>,
     network: &'a NET,
+    // Index of the move played in each generation of the tree.
+    played: Vec<usize>,
+    // Number of simulations planned for the search at the root.
+    simulations: u32,
 
     request_tx: Sender<Request<N>>,
     response_rx: Receiver<Response<N>>,
-    batch: u32,
+    config: SearchConfig,
 
     save_examples: bool,
     create_analysis: bool,
 
     examples: Vec<IncompleteExample<N>>,
     analysis: Analysis,
+    ply: u16,
+
+    search_start: Instant,
+    start_visits: u32,
 }
 
-impl<'a, const N: usize, NET: Network<N>> Player<'a, N, NET> {
+impl<'a, const N: usize, NET: Evaluator<N>> Player<'a, N, NET> {
     pub fn new(
         network: &'a NET,
-        batch: u32,
+        config: SearchConfig,
         save_examples: bool,
         create_analysis: bool,
         game: &Game<N>,
@@ -46,124 +60,303 @@ impl<'a, const N: usize, NET: Network<N>> Player<'a, N, NET> {
         let (request_tx, request_rx) = channel();
         let (response_tx, response_rx) = channel();
 
+        let simulations = config.rollouts.saturating_mul(config.batch_size);
+        let analysis =
+            Analysis::new(N as u8, game.half_komi, game.ply).with_multi_pv(config.multi_pv as usize);
         let instance = Self {
-            node: Default::default(),
+            tree: Default::default(),
+            gumbel: Default::default(),
             network,
+            played: Vec::new(),
+            simulations,
             request_tx,
             response_rx,
-            batch,
+            config,
             save_examples,
             create_analysis,
             examples: Vec::new(),
-            analysis: Analysis::new(N as u8, game.half_komi, game.ply),
+            analysis,
+            ply: game.ply,
+            search_start: Instant::now(),
+            start_visits: 0,
         };
 
-        // Create virtual rollout thread.
-        let node = instance.node.clone();
-        Self::run_rollout_thread(node, request_rx, response_tx);
+        // Create the virtual rollout threads, each of which keeps one batch in flight.
+        let request_rx = Arc::new(Mutex::new(request_rx));
+        for _ in 0..instance.threads() {
+            Self::run_rollout_thread(
+                instance.tree.clone(),
+                instance.gumbel.clone(),
+                request_rx.clone(),
+                response_tx.clone(),
+                instance.config.clone(),
+            );
+        }
 
-        // Request the first batch.
-        instance.request_batch(game);
+        // Request the first batches.
+        instance.request_batches(game);
 
         instance
     }
 
     fn run_rollout_thread(
-        node: Arc<Mutex<Node>>,
-        request_rx: Receiver<Request<N>>,
+        tree: Arc<RwLock<Tree>>,
+        gumbel: Arc<Mutex<Option<GumbelRoot>>>,
+        request_rx: Arc<Mutex<Receiver<Request<N>>>>,
         response_tx: Sender<Response<N>>,
+        config: SearchConfig,
     ) {
-        spawn(move || {
-            while let Ok((game, batch)) = request_rx.recv() {
-                let mut node = node.lock().unwrap();
-                let paths: (Vec<_>, Vec<_>) = (0..batch)
-                    .filter_map(|_| {
-                        let mut path = vec![];
-                        let mut game = game.clone();
-                        if node.virtual_rollout(&mut game, &mut path) == GameResult::Ongoing {
-                            Some((path, game))
-                        } else {
-                            None
-                        }
-                    })
-                    .unzip();
-
-                if response_tx.send(paths).is_err() {
-                    break;
-                };
-            }
+        spawn(move || loop {
+            // Only hold the receiver while waiting, so that other threads
+            // can take the next request.
+            let request = request_rx.lock().unwrap().recv();
+            let (game, batch, generation) = match request {
+                Ok(request) => request,
+                Err(_) => break,
+            };
+            // The tree is only locked for reading, so the other threads
+            // descend it at the same time.
+            let tree = tree.read().unwrap();
+            // A move was played since the request was made,
+            // so the game no longer belongs to the root.
+            let batch = if tree.generation() == generation { batch } else { 0 };
+            let (paths, games) = (0..batch)
+                .filter_map(|_| {
+                    let mut path = vec![];
+                    let mut game = game.clone();
+                    let root = tree.root();
+                    // Only hold the lock while choosing the move at the root.
+                    let action = gumbel
+                        .lock()
+                        .unwrap()
+                        .as_mut()
+                        .and_then(|gumbel| gumbel.next_action(root));
+                    let result = match action {
+                        Some(action) => root.gumbel_rollout(action, &mut game, &mut path, &config),
+                        None => root.virtual_rollout(&mut game, &mut path, &config),
+                    };
+                    if result == GameResult::Ongoing {
+                        Some((path, game))
+                    } else {
+                        None
+                    }
+                })
+                .unzip();
+            drop(tree);
+
+            if response_tx.send((generation, paths, games)).is_err() {
+                break;
+            };
         });
     }
 
+    /// Number of batches in flight, one for each rollout thread.
+    fn threads(&self) -> u32 {
+        self.config.threads.max(1)
+    }
+
     fn request_batch(&self, game: &Game<N>) {
-        self.request_tx.send((game.clone(), self.batch)).unwrap();
+        let generation = self.played.len() as u32;
+        self.request_tx
+            .send((game.clone(), self.config.batch_size, generation))
+            .unwrap();
+    }
+
+    fn request_batches(&self, game: &Game<N>) {
+        for _ in 0..self.threads() {
+            self.request_batch(game);
+        }
     }
 
     fn consume_batch(&self) {
-        let (paths, games) = self.response_rx.recv().unwrap();
-        let net_outputs = self.network.policy_eval(games.as_slice());
+        let (generation, paths, games) = self.response_rx.recv().unwrap();
 
-        let mut node = self.node.lock().unwrap();
+        // Keep the paths which continue under the moves played since the
+        // batch was requested, relative to the new root. The others were
+        // discarded with the rest of the tree, so they are not evaluated.
+        let played = &self.played[generation as usize..];
+        let (paths, games): (Vec<_>, Vec<_>) = paths
+            .into_iter()
+            .zip(games)
+            .filter(|(path, _game)| path.starts_with(played))
+            .map(|(path, game)| (path[played.len()..].to_vec(), game))
+            .unzip();
+        let net_outputs = self.network.evaluate_batch(games.as_slice());
+
+        let tree = self.tree.read().unwrap();
         net_outputs.into_iter().zip(paths).for_each(|(result, path)| {
-            node.devirtualize_path::<N, _>(&mut path.into_iter(), &result);
+            tree.root()
+                .devirtualize_path::<N, _>(&mut path.into_iter(), &result);
         });
     }
 
+    /// Evaluate every batch in flight, after which no thread is using the tree.
+    fn consume_batches(&self) {
+        for _ in 0..self.threads() {
+            self.consume_batch();
+        }
+    }
+
     /// Get the debug info for the node.
     pub fn debug(&self, depth: usize) -> NodeDebugInfo {
-        self.node.lock().unwrap().debug(depth)
+        self.tree
+            .read()
+            .unwrap()
+            .root()
+            .debug(depth)
+            .with_logit_per_flat(self.config.logit_per_flat)
+    }
+
+    /// Get statistics about the search of the current position.
+    pub fn report(&self) -> SearchReport {
+        let tree = self.tree.read().unwrap();
+        SearchReport {
+            nodes_searched: tree.root().visits().saturating_sub(self.start_visits),
+            time: self.search_start.elapsed(),
+            ..SearchReport::from_node(tree.root(), MAX_PV_LENGTH)
+        }
+        .with_lines(tree.root(), self.config.multi_pv as usize, MAX_PV_LENGTH)
+        .with_logit_per_flat(self.config.logit_per_flat)
+    }
+
+    /// Get the search parameters.
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
     }
 
     /// Add noise to the policies at the current node.
-    pub fn add_noise(&mut self, alpha: f32, ratio: f32, game: &Game<N>) {
-        self.consume_batch();
-        self.node.lock().unwrap().apply_dirichlet(alpha, ratio);
-        self.request_batch(game)
+    pub fn add_noise(&mut self, game: &Game<N>) {
+        self.consume_batches();
+        self.tree
+            .read()
+            .unwrap()
+            .root()
+            .apply_dirichlet(self.config.noise_alpha, self.config.noise_ratio);
+        self.request_batches(game)
+    }
+
+    /// Only search the moves allowed by the filter in the current position.
+    /// The filter is dropped once a move is played.
+    /// Returns `false` if the filter allows no legal move, in which case
+    /// every move is searched.
+    pub fn restrict_moves(&mut self, game: &Game<N>, filter: &MoveFilter) -> bool {
+        // Evaluate the batches in flight, so that the root is expanded and
+        // no paths hold indices into its children.
+        self.consume_batches();
+        let allowed = self.tree.write().unwrap().retain_moves(filter);
+        // Sequential halving has to sample again from the remaining moves.
+        *self.gumbel.lock().unwrap() = None;
+        self.request_batches(game);
+        allowed
+    }
+
+    /// Plan how many calls to [`Player::rollout`] the search of the current
+    /// position will make. Sequential halving splits them between the
+    /// considered moves, so this should be called before searching
+    /// with a different budget than `config.rollouts`.
+    pub fn plan_rollouts(&mut self, rollouts: u32) {
+        let simulations = rollouts.saturating_mul(self.config.batch_size);
+        if simulations != self.simulations {
+            self.simulations = simulations;
+            *self.gumbel.lock().unwrap() = None;
+        }
     }
 
     /// Do a batch of rollouts.
     pub fn rollout(&mut self, game: &Game<N>) {
         self.request_batch(game);
         self.consume_batch();
+        self.collect_garbage();
+        self.start_gumbel();
+    }
+
+    /// Sample the moves for sequential halving once the root policy is known.
+    fn start_gumbel(&self) {
+        // Lock in the same order as the rollout threads.
+        let tree = self.tree.read().unwrap();
+        let mut gumbel = self.gumbel.lock().unwrap();
+        if gumbel.is_none() {
+            *gumbel = tree.root().gumbel_root(&self.config, self.simulations);
+        }
+    }
+
+    /// Collapse rarely visited subtrees once the tree exceeds the memory
+    /// limit. Paths which are in flight hold virtual visits, so they are kept.
+    fn collect_garbage(&self) {
+        let limit = self.config.memory_limit_bytes();
+        if self.tree.read().unwrap().memory() > limit {
+            self.tree.write().unwrap().collect_garbage(limit);
+        }
+    }
+
+    /// Check whether the best move can no longer change within the given
+    /// number of calls to [`Player::rollout`].
+    pub fn is_decided(&self, rollouts_left: u32) -> bool {
+        // Include the batches which are still in flight.
+        let visits_left = rollouts_left
+            .saturating_add(self.threads())
+            .saturating_mul(self.config.batch_size);
+        self.tree.read().unwrap().root().is_decided(visits_left)
     }
 
     /// Pick a move to play.
+    /// When not exploiting, the move is sampled using the temperature
+    /// schedule, or taken from sequential halving when using Gumbel
+    /// root selection.
     pub fn pick_move(&mut self, exploitation: bool) -> Move {
-        self.node.lock().unwrap().pick_move(exploitation)
+        let tree = self.tree.read().unwrap();
+        if !exploitation {
+            if let Some(gumbel) = self.gumbel.lock().unwrap().as_ref() {
+                return gumbel.pick_move(tree.root());
+            }
+        }
+        let temperature = if exploitation {
+            0.0
+        } else {
+            self.config.temperature_at(self.ply)
+        };
+        tree.root()
+            .pick_move_pruned(temperature, self.config.min_visit_ratio)
     }
 
     /// Update the search tree, analysis, and create an example.
     pub fn play_move(&mut self, my_move: Move, game: &Game<N>, with_info: bool) {
-        // rollout stale paths
-        // necessary to update policies accordingly
-        // TODO: avoid rolling out nodes that are going to be discarded
-        self.consume_batch();
-
-        let mut node = self.node.lock().unwrap();
+        // Batches in flight are not waited for. Their paths are moved to
+        // the new root once they arrive.
+        let mut tree = self.tree.write().unwrap();
 
         // Save example.
         if self.save_examples && with_info {
             self.examples.push(IncompleteExample {
                 game: game.clone(),
-                policy: node.improved_policy(),
+                policy: if self.config.uses_gumbel() {
+                    tree.root().completed_policy()
+                } else {
+                    tree.root().improved_policy()
+                },
             });
         }
 
         // Update analysis.
         if self.create_analysis {
             if with_info {
-                self.analysis.update(&node, my_move);
+                self.analysis.update(tree.root(), my_move);
             } else {
                 self.analysis.add_move_without_info(my_move)
             }
         }
 
-        *node = std::mem::take(node.deref_mut()).play(my_move);
+        let index = tree.play(my_move);
+        self.played.push(index);
+        *self.gumbel.lock().unwrap() = None;
 
-        // Refill queue.
         let mut game = game.clone();
         game.play(my_move).unwrap();
-        self.request_batch(&game);
+        self.ply = game.ply;
+        // The new root was expanded with the shallower interior search.
+        tree.root().solve_tactics(&game, self.config.root_tactics_depth);
+        self.start_visits = tree.root().visits();
+        self.search_start = Instant::now();
     }
 
     /// Complete collected examples with the game result and return them.
@@ -192,6 +385,13 @@ impl<'a, const N: usize, NET: Network<N>> Player<'a, N, NET> {
             .collect()
     }
 
+    /// Complete collected examples with the result and auxiliary targets
+    /// of a game which was played until the end, and return them.
+    /// The examples in the Player will be empty after this method is used.
+    pub fn get_examples_with_targets(&mut self, end: &Game<N>) -> Vec<Example<N>> {
+        complete_game(std::mem::take(&mut self.examples), end)
+    }
+
     /// Get the analysis of the game
     pub fn get_analysis(&mut self) -> Analysis {
         std::mem::take(&mut self.analysis)
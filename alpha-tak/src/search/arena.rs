@@ -0,0 +1,243 @@
+use std::{
+    fmt::Debug,
+    mem::size_of,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Mutex,
+        OnceLock,
+    },
+};
+
+use tak::*;
+
+use super::node::{Edge, Expansion, Node, NodeRef};
+
+/// Index of a node in an [`Arena`].
+pub type NodeId = u32;
+
+// Size of the first segment. Each following segment is twice as large.
+const FIRST_SEGMENT: usize = 1024;
+// Enough segments to address every node id.
+const SEGMENTS: usize = 22;
+
+/// Storage for the nodes of a search tree.
+///
+/// Nodes are allocated in segments of doubling size which never move,
+/// so that threads can share the arena while new nodes are added.
+/// Nodes of discarded subtrees are reset and handed out again.
+#[derive(Default)]
+pub struct Arena {
+    segments: [OnceLock<Box<[Node]>>; SEGMENTS],
+    len: AtomicU32,
+    free: Mutex<Vec<NodeId>>,
+    // Number of edges in the expansions of the nodes in use.
+    edges: AtomicUsize,
+}
+
+fn locate(id: NodeId) -> (usize, usize) {
+    let index = id as usize + FIRST_SEGMENT;
+    let segment = (index.ilog2() - FIRST_SEGMENT.ilog2()) as usize;
+    (segment, index - (FIRST_SEGMENT << segment))
+}
+
+impl Arena {
+    pub fn get(&self, id: NodeId) -> &Node {
+        let (segment, offset) = locate(id);
+        &self.segments[segment].get().expect("node is not allocated")[offset]
+    }
+
+    fn get_mut(&mut self, id: NodeId) -> &mut Node {
+        let (segment, offset) = locate(id);
+        &mut self.segments[segment].get_mut().expect("node is not allocated")[offset]
+    }
+
+    /// Get a fresh node, reusing a freed one if possible.
+    pub fn alloc(&self) -> NodeId {
+        if let Some(id) = self.free.lock().unwrap().pop() {
+            return id;
+        }
+        let id = self.len.fetch_add(1, Ordering::Relaxed);
+        let (segment, _offset) = locate(id);
+        self.segments[segment]
+            .get_or_init(|| (0..FIRST_SEGMENT << segment).map(|_| Node::default()).collect());
+        id
+    }
+
+    /// Return a node which was never used.
+    pub(super) fn release(&self, id: NodeId) {
+        self.free.lock().unwrap().push(id);
+    }
+
+    pub(super) fn add_edges(&self, edges: usize) {
+        self.edges.fetch_add(edges, Ordering::Relaxed);
+    }
+
+    // Account for the edges of a removed expansion and get its children.
+    fn remove_expansion(&self, expansion: Option<Expansion>) -> Vec<NodeId> {
+        let children = expansion.map(|expansion| expansion.children).unwrap_or_default();
+        self.edges.fetch_sub(children.len(), Ordering::Relaxed);
+        children.iter().filter_map(Edge::child).collect()
+    }
+
+    /// Reset the subtree at `root` for reuse, except for the subtree at
+    /// `keep`.
+    pub fn free_tree(&mut self, root: NodeId, keep: Option<NodeId>) {
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            let node = std::mem::take(self.get_mut(id));
+            let children = self.remove_expansion(node.expansion.into_inner());
+            stack.extend(children.into_iter().filter(|&child| Some(child) != keep));
+            self.free.get_mut().unwrap().push(id);
+        }
+    }
+
+    /// Free the children of a node. The node keeps its statistics
+    /// and is expanded again on its next visit.
+    pub fn collapse(&mut self, id: NodeId) {
+        let expansion = self.get_mut(id).expansion.take();
+        for child in self.remove_expansion(expansion) {
+            self.free_tree(child, None);
+        }
+    }
+
+    /// Remove the edges of a node which are not kept and free their
+    /// subtrees.
+    pub(super) fn retain_edges(&mut self, id: NodeId, keep: impl Fn(&Edge) -> bool) {
+        let expansion = match self.get_mut(id).expansion.take() {
+            Some(expansion) => expansion,
+            None => return,
+        };
+        let (kept, removed): (Vec<Edge>, Vec<Edge>) = expansion
+            .children
+            .into_vec()
+            .into_iter()
+            .partition(|edge| keep(edge));
+        for child in self.remove_expansion(Some(Expansion {
+            result: expansion.result,
+            children: removed.into(),
+        })) {
+            self.free_tree(child, None);
+        }
+        self.get_mut(id).expansion = OnceLock::from(Expansion {
+            result: expansion.result,
+            children: kept.into(),
+        });
+    }
+
+    /// Number of nodes which are in use.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed) as usize - self.free.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of nodes which were allocated, including freed ones.
+    pub fn allocated(&self) -> usize {
+        self.len.load(Ordering::Relaxed) as usize
+    }
+
+    /// Memory used by the nodes and edges in use, in bytes.
+    pub fn memory(&self) -> usize {
+        self.len() * size_of::<Node>() + self.edges.load(Ordering::Relaxed) * size_of::<Edge>()
+    }
+}
+
+impl Debug for Arena {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Arena").field("len", &self.len()).finish()
+    }
+}
+
+/// A search tree, with its nodes stored in an [`Arena`].
+#[derive(Debug)]
+pub struct Tree {
+    arena: Arena,
+    root: NodeId,
+    generation: u32,
+}
+
+impl Default for Tree {
+    fn default() -> Self {
+        let arena = Arena::default();
+        let root = arena.alloc();
+        Tree {
+            arena,
+            root,
+            generation: 0,
+        }
+    }
+}
+
+impl Tree {
+    pub fn root(&self) -> NodeRef<'_> {
+        NodeRef::new(&self.arena, self.root)
+    }
+
+    pub fn arena(&self) -> &Arena {
+        &self.arena
+    }
+
+    pub(super) fn arena_mut(&mut self) -> &mut Arena {
+        &mut self.arena
+    }
+
+    /// Number of moves played on this tree. Paths from the root which
+    /// were made in an earlier generation start with the played moves.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Make the subtree of the given move the new root.
+    /// The rest of the tree is freed for reuse.
+    /// Returns the index of the move among the children of the old root.
+    pub fn play(&mut self, my_move: Move) -> usize {
+        let (index, (_mov, edge)) = self
+            .root()
+            .children()
+            .enumerate()
+            .find(|(_index, (mov, _edge))| mov == &my_move)
+            .expect("tried to play an invalid move");
+        let child = edge.node_or_insert().id();
+
+        self.arena.free_tree(self.root, Some(child));
+        self.root = child;
+        self.generation += 1;
+        index
+    }
+
+    /// Memory used by the tree, in bytes.
+    pub fn memory(&self) -> usize {
+        self.arena.memory()
+    }
+
+    /// Free memory by collapsing the least visited subtrees until the tree
+    /// fits within `limit` bytes. Collapsed nodes keep their statistics.
+    /// Nodes on paths which are waiting for the network are never collapsed.
+    pub fn collect_garbage(&mut self, limit: usize) {
+        let mut threshold = 1;
+        while self.memory() > limit && threshold <= self.root().visits() {
+            threshold = threshold.saturating_mul(2);
+            self.collapse(threshold);
+        }
+    }
+
+    // Collapse every node below the root with fewer visits than the threshold.
+    fn collapse(&mut self, threshold: u32) {
+        let mut collapsed = Vec::new();
+        let mut stack = vec![self.root()];
+        while let Some(node) = stack.pop() {
+            for child in node.children().filter_map(|(_mov, edge)| edge.node()) {
+                if child.virtual_visits() == 0 && child.visits() < threshold {
+                    collapsed.push(child.id());
+                } else {
+                    stack.push(child);
+                }
+            }
+        }
+        for id in collapsed {
+            self.arena.collapse(id);
+        }
+    }
+}
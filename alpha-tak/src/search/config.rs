@@ -0,0 +1,292 @@
+use std::{error::Error, fmt::Display, fs::read_to_string, path::Path, str::FromStr};
+
+use crate::model::score::DEFAULT_LOGIT_PER_FLAT;
+
+/// How the expected reward of unvisited children is chosen (first play
+/// urgency). The text format is `absolute:<value>` or `reduction:<value>`,
+/// and a bare number is read as an absolute value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fpu {
+    /// Use a fixed expected reward.
+    Absolute(f32),
+    /// Use the expected reward of the parent, reduced by the given amount
+    /// scaled by the square root of the policy of visited children.
+    Reduction(f32),
+}
+
+impl Display for Fpu {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fpu::Absolute(value) => write!(f, "absolute:{value}"),
+            Fpu::Reduction(reduction) => write!(f, "reduction:{reduction}"),
+        }
+    }
+}
+
+impl FromStr for Fpu {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("absolute", value)) => Ok(Fpu::Absolute(value.trim().parse()?)),
+            Some(("reduction", reduction)) => Ok(Fpu::Reduction(reduction.trim().parse()?)),
+            Some((kind, _)) => Err(format!("unknown fpu strategy {kind}"))?,
+            None => Ok(Fpu::Absolute(s.parse()?)),
+        }
+    }
+}
+
+/// How the move at the root is chosen for each rollout.
+/// The text format is `puct` or `gumbel:<considered moves>`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RootSelection {
+    /// Select like at every other node.
+    Puct,
+    /// Sample the given number of moves with Gumbel noise
+    /// and split the rollouts between them with sequential halving.
+    Gumbel(u32),
+}
+
+impl Display for RootSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RootSelection::Puct => write!(f, "puct"),
+            RootSelection::Gumbel(considered) => write!(f, "gumbel:{considered}"),
+        }
+    }
+}
+
+impl FromStr for RootSelection {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "puct" => Ok(RootSelection::Puct),
+            Some(("gumbel", considered)) => Ok(RootSelection::Gumbel(considered.trim().parse()?)),
+            _ => Err(format!("unknown root selection {s}"))?,
+        }
+    }
+}
+
+/// Parameters which control the search.
+/// The text format has one `key = value` pair per line
+/// and lines starting with `#` are ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchConfig {
+    /// Controls how slowly the exploration rate grows with visits.
+    pub exploration_base: f32,
+    /// Exploration rate of a node without visits.
+    pub exploration_init: f32,
+    /// Expected reward assumed for unvisited children below the root.
+    pub fpu: Fpu,
+    /// Expected reward assumed for unvisited children of the root.
+    pub root_fpu: Fpu,
+    /// Reward subtracted for each virtual visit on a path that is waiting for
+    /// the network.
+    pub virtual_loss: f32,
+    /// Concentration of the dirichlet noise added to the root policy.
+    pub noise_alpha: f32,
+    /// How much of the root policy is replaced by noise.
+    pub noise_ratio: f32,
+    /// Noise is only added before this ply.
+    pub noise_plies: u16,
+    /// Temperature used when sampling moves proportional to visits,
+    /// at the start of the game.
+    pub temperature: f32,
+    /// Temperature which the schedule decays towards.
+    pub final_temperature: f32,
+    /// Plies after which the temperature has decayed halfway to the final
+    /// temperature. Zero keeps the temperature constant.
+    pub temperature_half_life: u16,
+    /// Moves with fewer visits than this fraction of the most visited move
+    /// are never sampled.
+    pub min_visit_ratio: f32,
+    /// Moves are sampled before this ply and picked greedily after.
+    pub exploit_plies: u16,
+    /// Number of virtual rollouts sent to the network at once.
+    pub batch_size: u32,
+    /// Number of rollouts per move when searching with a fixed budget.
+    pub rollouts: u32,
+    /// Number of rollouts of a fast search in self-play.
+    /// Fast searches are not used as policy targets.
+    pub fast_rollouts: u32,
+    /// Fraction of self-play moves which use a full search.
+    pub full_search_ratio: f32,
+    /// Number of threads doing virtual rollouts for a player. Each keeps a
+    /// batch in flight, so that descending the tree overlaps with evaluation.
+    pub threads: u32,
+    /// Depth in plies of the forced win search when expanding a node.
+    /// Zero disables it, since it runs for every new node.
+    pub tactics_depth: u16,
+    /// Depth in plies of the forced win search at the root.
+    pub root_tactics_depth: u16,
+    /// How the move at the root is chosen.
+    pub root_selection: RootSelection,
+    /// Number of root moves which are guaranteed a share of the visits
+    /// and reported with their own principal variation (MultiPV).
+    pub multi_pv: u32,
+    /// Fraction of the root visits split evenly between the MultiPV moves.
+    pub multi_pv_share: f32,
+    /// Memory the search tree may use in MiB. Once exceeded,
+    /// the least visited subtrees are collapsed.
+    pub memory_limit: usize,
+    /// How much one flat of advantage changes the logit of the win
+    /// probability, used to report scores in centiflats.
+    pub logit_per_flat: f32,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            exploration_base: 500.0,
+            exploration_init: 4.0,
+            fpu: Fpu::Absolute(0.0),
+            root_fpu: Fpu::Absolute(0.0),
+            virtual_loss: 1.0,
+            noise_alpha: 0.2,
+            noise_ratio: 0.3,
+            noise_plies: 80,
+            temperature: 1.0,
+            final_temperature: 0.0,
+            temperature_half_life: 0,
+            min_visit_ratio: 0.0,
+            exploit_plies: 40,
+            batch_size: 32,
+            rollouts: 10_000,
+            fast_rollouts: 2_000,
+            full_search_ratio: 1.0,
+            threads: 1,
+            tactics_depth: 0,
+            root_tactics_depth: 3,
+            root_selection: RootSelection::Puct,
+            multi_pv: 1,
+            multi_pv_share: 0.5,
+            memory_limit: 4096,
+            logit_per_flat: DEFAULT_LOGIT_PER_FLAT,
+        }
+    }
+}
+
+impl SearchConfig {
+    /// Load a config from a file. Missing keys keep their default values.
+    pub fn load<T: AsRef<Path>>(path: T) -> Result<Self, Box<dyn Error>> {
+        read_to_string(path)?.parse()
+    }
+
+    /// Create a config from an optional file and a list of `key=value`
+    /// overrides, as given on the command line.
+    pub fn from_args(path: Option<&str>, overrides: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut config = match path {
+            Some(path) => Self::load(path)?,
+            None => Self::default(),
+        };
+        for pair in overrides {
+            let (key, value) = pair.split_once('=').ok_or("override has missing delimiter")?;
+            config.set(key.trim(), value.trim())?;
+        }
+        Ok(config)
+    }
+
+    /// Set a single parameter by name.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+        match key {
+            "exploration_base" => self.exploration_base = value.parse()?,
+            "exploration_init" => self.exploration_init = value.parse()?,
+            "fpu" => self.fpu = value.parse()?,
+            "root_fpu" => self.root_fpu = value.parse()?,
+            "virtual_loss" => self.virtual_loss = value.parse()?,
+            "noise_alpha" => self.noise_alpha = value.parse()?,
+            "noise_ratio" => self.noise_ratio = value.parse()?,
+            "noise_plies" => self.noise_plies = value.parse()?,
+            "temperature" => self.temperature = value.parse()?,
+            "final_temperature" => self.final_temperature = value.parse()?,
+            "temperature_half_life" => self.temperature_half_life = value.parse()?,
+            "min_visit_ratio" => self.min_visit_ratio = value.parse()?,
+            "exploit_plies" => self.exploit_plies = value.parse()?,
+            "batch_size" => self.batch_size = value.parse()?,
+            "rollouts" => self.rollouts = value.parse()?,
+            "fast_rollouts" => self.fast_rollouts = value.parse()?,
+            "full_search_ratio" => self.full_search_ratio = value.parse()?,
+            "threads" => self.threads = value.parse()?,
+            "tactics_depth" => self.tactics_depth = value.parse()?,
+            "root_tactics_depth" => self.root_tactics_depth = value.parse()?,
+            "root_selection" => self.root_selection = value.parse()?,
+            "multi_pv" => self.multi_pv = value.parse()?,
+            "multi_pv_share" => self.multi_pv_share = value.parse()?,
+            "memory_limit" => self.memory_limit = value.parse()?,
+            "logit_per_flat" => self.logit_per_flat = value.parse()?,
+            _ => Err(format!("unknown search parameter {key}"))?,
+        }
+        Ok(())
+    }
+
+    /// Temperature for sampling moves at the given ply.
+    pub fn temperature_at(&self, ply: u16) -> f32 {
+        if self.temperature_half_life == 0 {
+            return self.temperature;
+        }
+        let decay = 0.5f32.powf(ply as f32 / self.temperature_half_life as f32);
+        self.final_temperature + (self.temperature - self.final_temperature) * decay
+    }
+
+    /// Check whether moves at the root are chosen with Gumbel sampling.
+    pub fn uses_gumbel(&self) -> bool {
+        matches!(self.root_selection, RootSelection::Gumbel(_))
+    }
+
+    /// Memory limit of the search tree in bytes.
+    pub fn memory_limit_bytes(&self) -> usize {
+        self.memory_limit.saturating_mul(1 << 20)
+    }
+
+    /// Exploration rate C(s) for a node with the given visit count.
+    pub fn exploration_rate(&self, visits: f32) -> f32 {
+        ((1.0 + visits + self.exploration_base) / self.exploration_base).ln() + self.exploration_init
+    }
+}
+
+impl Display for SearchConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "exploration_base = {}", self.exploration_base)?;
+        writeln!(f, "exploration_init = {}", self.exploration_init)?;
+        writeln!(f, "fpu = {}", self.fpu)?;
+        writeln!(f, "root_fpu = {}", self.root_fpu)?;
+        writeln!(f, "virtual_loss = {}", self.virtual_loss)?;
+        writeln!(f, "noise_alpha = {}", self.noise_alpha)?;
+        writeln!(f, "noise_ratio = {}", self.noise_ratio)?;
+        writeln!(f, "noise_plies = {}", self.noise_plies)?;
+        writeln!(f, "temperature = {}", self.temperature)?;
+        writeln!(f, "final_temperature = {}", self.final_temperature)?;
+        writeln!(f, "temperature_half_life = {}", self.temperature_half_life)?;
+        writeln!(f, "min_visit_ratio = {}", self.min_visit_ratio)?;
+        writeln!(f, "exploit_plies = {}", self.exploit_plies)?;
+        writeln!(f, "batch_size = {}", self.batch_size)?;
+        writeln!(f, "rollouts = {}", self.rollouts)?;
+        writeln!(f, "fast_rollouts = {}", self.fast_rollouts)?;
+        writeln!(f, "full_search_ratio = {}", self.full_search_ratio)?;
+        writeln!(f, "threads = {}", self.threads)?;
+        writeln!(f, "tactics_depth = {}", self.tactics_depth)?;
+        writeln!(f, "root_tactics_depth = {}", self.root_tactics_depth)?;
+        writeln!(f, "root_selection = {}", self.root_selection)?;
+        writeln!(f, "multi_pv = {}", self.multi_pv)?;
+        writeln!(f, "multi_pv_share = {}", self.multi_pv_share)?;
+        writeln!(f, "memory_limit = {}", self.memory_limit)?;
+        writeln!(f, "logit_per_flat = {}", self.logit_per_flat)
+    }
+}
+
+impl FromStr for SearchConfig {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = SearchConfig::default();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or("line has missing delimiter")?;
+            config.set(key.trim(), value.trim())?;
+        }
+        Ok(config)
+    }
+}
@@ -2,42 +2,51 @@ use std::{collections::VecDeque, fmt::Display};
 
 use tak::*;
 
-use super::node::Node;
+use super::node::{NodeRef, Proof};
+use crate::model::score::{centiflats, win_probability, DEFAULT_LOGIT_PER_FLAT};
 
-impl Node {
+impl NodeRef<'_> {
     /// Get debug info for this node.
     pub fn debug(&self, depth: usize) -> NodeDebugInfo {
         let mut moves: Vec<_> = self
-            .children
-            .iter()
-            .map(|(mov, node)| MoveInfo {
-                mov: *mov,
-                visits: node.visits,
-                reward: node.expected_reward,
-                policy: node.policy,
-                continuation: node.continuation(depth),
+            .children()
+            .map(|(mov, edge)| MoveInfo {
+                mov,
+                visits: edge.visits(),
+                reward: edge.expected_reward(),
+                draw: edge.draw_probability(),
+                policy: edge.policy(),
+                proof: edge.proof(),
+                continuation: edge
+                    .node()
+                    .map(|node| node.continuation(depth))
+                    .unwrap_or_default(),
             })
             .collect();
         moves.sort_unstable_by_key(|info| info.visits);
         moves.reverse();
-        NodeDebugInfo(moves)
+        NodeDebugInfo(moves, DEFAULT_LOGIT_PER_FLAT)
     }
 
     pub fn continuation(&self, depth: usize) -> VecDeque<(Move, u32)> {
-        if depth == 0 || self.children.is_empty() {
+        if depth == 0 || self.children().len() == 0 {
             return VecDeque::new();
         }
-        let my_move = self.pick_move(true);
-        let (_mov, node) = self.children.iter().find(|(mov, _node)| mov == &my_move).unwrap();
-        let mut turns = node.continuation(depth - 1);
-        turns.push_front((my_move, node.visits));
+        let my_move = self.pick_move(0.0);
+        let (_mov, edge) = self.children().find(|(mov, _edge)| mov == &my_move).unwrap();
+        let mut turns = edge
+            .node()
+            .map(|node| node.continuation(depth - 1))
+            .unwrap_or_default();
+        turns.push_front((my_move, edge.visits()));
         turns
     }
 }
 
 /// The inner Vec should always be sorted in descending order of visits.
+/// The second field is the scale used to display the score in centiflats.
 #[derive(Debug, Clone)]
-pub struct NodeDebugInfo(pub(crate) Vec<MoveInfo>);
+pub struct NodeDebugInfo(pub(crate) Vec<MoveInfo>, pub(crate) f32);
 
 impl NodeDebugInfo {
     pub fn eval(&self) -> f32 {
@@ -48,9 +57,19 @@ impl NodeDebugInfo {
             .sum()
     }
 
+    /// Display the score in centiflats with a calibrated scale.
+    #[must_use]
+    pub fn with_logit_per_flat(mut self, logit_per_flat: f32) -> NodeDebugInfo {
+        self.1 = logit_per_flat;
+        self
+    }
+
     pub fn maybe_flip(mut self, flip: bool) -> NodeDebugInfo {
         if flip {
-            self.0.iter_mut().for_each(|move_info| move_info.reward *= -1.);
+            self.0.iter_mut().for_each(|move_info| {
+                move_info.reward *= -1.;
+                move_info.proof = move_info.proof.flip();
+            });
         }
         self
     }
@@ -62,8 +81,14 @@ impl Display for NodeDebugInfo {
         if self.0.is_empty() {
             return write!(f, "Node has no children");
         }
-        writeln!(f, "evaluation: {:+.4}", self.eval())?;
-        writeln!(f, "turn      visited   reward   policy | continuation")?;
+        let eval = self.eval();
+        writeln!(
+            f,
+            "evaluation: {eval:+.4} (win probability {:.1}%, score {:+})",
+            100.0 * win_probability(eval),
+            centiflats(eval, self.1)
+        )?;
+        writeln!(f, "turn      visited   reward     draw   policy | continuation")?;
         for move_info in self.0.iter().take(f.precision().unwrap_or(usize::MAX)) {
             move_info.fmt(f)?
         }
@@ -76,14 +101,24 @@ pub struct MoveInfo {
     pub mov: Move,
     pub visits: u32,
     pub reward: f32,
+    /// Probability that the game ends in a draw.
+    pub draw: f32,
     pub policy: f32,
+    pub proof: Proof,
     pub continuation: VecDeque<(Move, u32)>,
 }
 
 impl MoveInfo {
     pub fn ptn_comment(&self, flip_reward: bool) -> String {
         let eval = if flip_reward { -self.reward } else { self.reward };
-        format!(" {{r: {:+.3}, p: {:.4}, v: {}}}", eval, self.policy, self.visits)
+        if self.draw > 0.0 {
+            format!(
+                " {{r: {:+.3}, d: {:.3}, p: {:.4}, v: {}}}",
+                eval, self.draw, self.policy, self.visits
+            )
+        } else {
+            format!(" {{r: {:+.3}, p: {:.4}, v: {}}}", eval, self.policy, self.visits)
+        }
     }
 }
 
@@ -91,11 +126,17 @@ impl Display for MoveInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(
             f,
-            "{: <8} {: >8} {: >+8.4} {: >8.4} | {}",
+            "{: <8} {: >8} {: >+8.4} {: >8.4} {: >8.4} | {}{}",
             self.mov.to_string(),
             self.visits,
             self.reward,
+            self.draw,
             self.policy,
+            if self.proof == Proof::Unknown {
+                String::new()
+            } else {
+                format!("({}) ", self.proof)
+            },
             self.continuation
                 .iter()
                 .map(|(mov, _visits)| mov.to_string())
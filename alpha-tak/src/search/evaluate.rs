@@ -0,0 +1,71 @@
+use tak::*;
+
+use super::{
+    arena::Tree,
+    config::SearchConfig,
+    node::Proof,
+    report::{principal_variation, PvEntry},
+};
+use crate::model::network::Evaluator;
+
+const MAX_PV_LENGTH: usize = 20;
+
+/// The outcome of searching a single position.
+/// Values are from the perspective of the player to move.
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    /// The most visited move, or `None` if the game is over.
+    pub best_move: Option<Move>,
+    pub eval: f32,
+    pub proof: Proof,
+    pub visits: u32,
+    pub pv: Vec<PvEntry>,
+}
+
+/// Search a position with the given number of rollouts on the calling thread.
+/// Rollouts are sent to the network in batches of `config.batch_size`.
+/// The search stops early once the outcome of the position is proven.
+pub fn evaluate<const N: usize, NET: Evaluator<N>>(
+    game: &Game<N>,
+    network: &NET,
+    config: &SearchConfig,
+    budget: u32,
+) -> SearchResult {
+    let batch_size = config.batch_size.max(1);
+    let mut tree = Tree::default();
+
+    let mut rollouts = 0;
+    while rollouts < budget && tree.root().proof() == Proof::Unknown {
+        tree.collect_garbage(config.memory_limit_bytes());
+        let root = tree.root();
+        let (paths, games): (Vec<_>, Vec<_>) = (0..batch_size.min(budget - rollouts))
+            .filter_map(|_| {
+                let mut path = Vec::new();
+                let mut game = game.clone();
+                if root.virtual_rollout(&mut game, &mut path, config) == GameResult::Ongoing {
+                    Some((path, game))
+                } else {
+                    None
+                }
+            })
+            .unzip();
+        rollouts += batch_size.min(budget - rollouts);
+
+        if !games.is_empty() {
+            for (path, output) in paths.into_iter().zip(network.evaluate_batch(&games)) {
+                root.devirtualize_path::<N, _>(&mut path.into_iter(), &output);
+            }
+        }
+    }
+
+    let root = tree.root();
+    let best_move = (root.children().len() > 0).then(|| root.pick_move(0.0));
+    SearchResult {
+        best_move,
+        // The node stores values from the perspective of the previous player.
+        eval: -root.expected_reward(),
+        proof: root.proof().flip(),
+        visits: root.visits(),
+        pv: principal_variation(root, MAX_PV_LENGTH),
+    }
+}
@@ -0,0 +1,43 @@
+use tak::*;
+
+use super::{arena::Tree, node::Proof};
+
+/// Restricts which moves are searched at the root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MoveFilter {
+    /// Only search these moves.
+    Only(Vec<Move>),
+    /// Search every move except these.
+    Exclude(Vec<Move>),
+}
+
+impl MoveFilter {
+    pub fn allows(&self, mov: &Move) -> bool {
+        match self {
+            MoveFilter::Only(moves) => moves.contains(mov),
+            MoveFilter::Exclude(moves) => !moves.contains(mov),
+        }
+    }
+}
+
+impl Tree {
+    /// Remove the moves at the root which the filter does not allow,
+    /// so that they are never searched, noised or picked.
+    /// The root must be expanded and no rollouts may be waiting for the
+    /// network. If the filter allows no legal move, the tree is left
+    /// unchanged and `false` is returned.
+    pub fn retain_moves(&mut self, filter: &MoveFilter) -> bool {
+        let root = self.root();
+        assert_eq!(root.virtual_visits(), 0, "cannot filter moves during a rollout");
+        if !root.children().any(|(mov, _edge)| filter.allows(&mov)) {
+            return false;
+        }
+        let id = root.id();
+        self.arena_mut().retain_edges(id, |edge| filter.allows(&edge.mov));
+        // The proof may have come from one of the removed moves.
+        let root = self.root();
+        root.set_proof(Proof::Unknown);
+        root.update_proof();
+        true
+    }
+}
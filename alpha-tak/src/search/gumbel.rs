@@ -0,0 +1,193 @@
+use rand_distr::{Distribution, Gumbel};
+use tak::*;
+
+use super::{
+    config::{RootSelection, SearchConfig},
+    node::{NodeRef, Proof},
+};
+
+// Constants of the monotonic transformation of Q values from the paper.
+const C_VISIT: f32 = 50.0;
+const C_SCALE: f32 = 1.0;
+// Policy targets are stored as integer weights, so probabilities are scaled up.
+const POLICY_SCALE: f32 = 10_000.0;
+
+/// Scale a Q value in `[-1, 1]` by the visit count of the most visited move.
+fn sigma(q: f32, max_visits: u32) -> f32 {
+    (C_VISIT + max_visits as f32) * C_SCALE * (q + 1.0) / 2.0
+}
+
+fn logit(policy: f32) -> f32 {
+    policy.max(f32::MIN_POSITIVE).ln()
+}
+
+/// Root move selection from Gumbel AlphaZero
+/// ("Policy improvement by planning with Gumbel", Danihelka et al.).
+///
+/// A few moves are sampled without replacement using the Gumbel-top-k trick,
+/// and the simulations are split between them with sequential halving.
+/// This improves the policy even when only a few simulations are done.
+#[derive(Clone, Debug)]
+pub struct GumbelRoot {
+    gumbel: Vec<f32>,
+    considered: Vec<usize>,
+    simulations: Vec<u32>,
+    phases: u32,
+    budget: u32,
+    target: u32,
+}
+
+impl GumbelRoot {
+    /// Sample the moves to consider at an expanded root.
+    /// The budget is the number of simulations the search is expected to do.
+    pub fn new(node: NodeRef, considered: u32, budget: u32) -> Self {
+        assert!(node.visits() > 0, "gumbel root requires an evaluated node");
+        let distr = Gumbel::new(0.0, 1.0).unwrap();
+        let mut rng = rand::thread_rng();
+        let gumbel: Vec<f32> = node.children().map(|_| distr.sample(&mut rng)).collect();
+
+        let mut moves: Vec<usize> = (0..node.children().len()).collect();
+        let score = |i: usize| gumbel[i] + logit(node.edge(i).policy());
+        moves.sort_by(|&a, &b| score(b).partial_cmp(&score(a)).expect("tried comparing nan"));
+        moves.truncate((considered as usize).max(1));
+
+        let phases = (moves.len() as f32).log2().ceil().max(1.0) as u32;
+        let mut root = GumbelRoot {
+            simulations: vec![0; node.children().len()],
+            gumbel,
+            considered: moves,
+            phases,
+            budget,
+            target: 0,
+        };
+        root.target = root.phase_simulations();
+        root
+    }
+
+    /// Simulations given to each considered move in the current phase.
+    fn phase_simulations(&self) -> u32 {
+        (self.budget / (self.phases * self.considered.len().max(1) as u32)).max(1)
+    }
+
+    fn score(&self, node: NodeRef, index: usize) -> f32 {
+        let max_visits = node
+            .children()
+            .map(|(_mov, edge)| edge.visits())
+            .max()
+            .unwrap_or_default();
+        let edge = node.edge(index);
+        self.gumbel[index] + logit(edge.policy()) + sigma(edge.expected_reward(), max_visits)
+    }
+
+    /// Get the index of the root move which the next simulation should start
+    /// with, or `None` once every considered move is proven to lose.
+    pub fn next_action(&mut self, node: NodeRef) -> Option<usize> {
+        // Proven losses are never played, so they are not worth simulating.
+        self.considered.retain(|&i| !node.edge(i).proof().is_loss());
+        loop {
+            let index = *self.considered.iter().min_by_key(|&&i| self.simulations[i])?;
+            if self.considered.len() == 1 || self.simulations[index] < self.target {
+                self.simulations[index] += 1;
+                return Some(index);
+            }
+            // Every remaining move used up its share, so keep the better half.
+            let mut considered = std::mem::take(&mut self.considered);
+            considered.sort_by(|&a, &b| {
+                self.score(node, b)
+                    .partial_cmp(&self.score(node, a))
+                    .expect("tried comparing nan")
+            });
+            considered.truncate(considered.len().div_ceil(2));
+            self.considered = considered;
+            self.target += self.phase_simulations();
+        }
+    }
+
+    /// Get the move which survived sequential halving.
+    /// A proven win is played straight away, and proven losses are
+    /// only played when every move loses.
+    pub fn pick_move(&self, node: NodeRef) -> Move {
+        let proven_win = node
+            .children()
+            .any(|(_mov, edge)| matches!(edge.proof(), Proof::Win(_)));
+        let best = self
+            .considered
+            .iter()
+            .copied()
+            .filter(|&i| !node.edge(i).proof().is_loss())
+            .max_by(|&a, &b| {
+                self.score(node, a)
+                    .partial_cmp(&self.score(node, b))
+                    .expect("tried comparing nan")
+            });
+        match best {
+            Some(index) if !proven_win => node.edge(index).mov(),
+            // Fall back to the visit counts, which handle proven moves.
+            _ => node.pick_move(0.0),
+        }
+    }
+}
+
+impl NodeRef<'_> {
+    /// Start a gumbel root for this node once its policy is known.
+    /// The simulations are the number of rollouts the search is expected to
+    /// start from this node.
+    pub fn gumbel_root(&self, config: &SearchConfig, simulations: u32) -> Option<GumbelRoot> {
+        let considered = match config.root_selection {
+            RootSelection::Gumbel(considered) => considered,
+            RootSelection::Puct => return None,
+        };
+        if self.visits() == 0 || self.children().len() == 0 {
+            return None;
+        }
+        Some(GumbelRoot::new(*self, considered, simulations))
+    }
+
+    /// Generate the improved policy from completed Q values,
+    /// which is a valid target even when few moves were visited.
+    /// Unvisited moves use a mix of the value and the Q values of visited
+    /// moves.
+    pub fn completed_policy(&self) -> Vec<(Move, u32)> {
+        let total_policy: f32 = self.children().map(|(_mov, edge)| edge.policy()).sum();
+        let prior = |policy: f32| policy / total_policy.max(f32::MIN_POSITIVE);
+
+        let visits: u32 = self.children().map(|(_mov, edge)| edge.visits()).sum();
+        let max_visits = self
+            .children()
+            .map(|(_mov, edge)| edge.visits())
+            .max()
+            .unwrap_or_default();
+        let mut visited_policy = 0.0;
+        let mut weighted_q = 0.0;
+        for (_mov, edge) in self.children().filter(|(_mov, edge)| edge.visits() > 0) {
+            visited_policy += prior(edge.policy());
+            weighted_q += prior(edge.policy()) * edge.expected_reward();
+        }
+        // The expected reward is stored from the perspective of the previous player.
+        let value = -self.expected_reward();
+        let mixed_value = if visited_policy > 0.0 {
+            (value + visits as f32 / visited_policy * weighted_q) / (1.0 + visits as f32)
+        } else {
+            value
+        };
+
+        let logits: Vec<f32> = self
+            .children()
+            .map(|(_mov, edge)| {
+                let q = if edge.visits() > 0 {
+                    edge.expected_reward()
+                } else {
+                    mixed_value
+                };
+                logit(prior(edge.policy())) + sigma(q, max_visits)
+            })
+            .collect();
+        let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let exps: Vec<f32> = logits.iter().map(|l| (l - max).exp()).collect();
+        let sum: f32 = exps.iter().sum();
+        self.children()
+            .zip(exps)
+            .map(|((mov, _edge), e)| (mov, (e / sum * POLICY_SCALE).round() as u32))
+            .collect()
+    }
+}
@@ -1,125 +1,262 @@
-use tak::{GameResult, *};
-
-use super::{move_map::move_index, node::Node};
-use crate::model::network::{Eval, Network, Policy};
+use std::sync::atomic::Ordering;
 
-// search
-const EXPLORATION_BASE: f32 = 500.0;
-const EXPLORATION_INIT: f32 = 4.0;
+use tak::{GameResult, *};
 
-fn exploration_rate(n: f32) -> f32 {
-    ((1.0 + n + EXPLORATION_BASE) / EXPLORATION_BASE).ln() + EXPLORATION_INIT
-}
+use super::{
+    config::SearchConfig,
+    move_map::move_index,
+    node::{EdgeRef, Expansion, Node, NodeRef, Proof},
+};
+use crate::model::network::{Eval, Evaluator, Policy};
 
-impl Node {
+impl NodeRef<'_> {
     /// Do a basic rollout.
-    pub fn rollout<const N: usize, NET: Network<N>>(&mut self, mut game: Game<N>, network: &NET) {
+    pub fn rollout<const N: usize, NET: Evaluator<N>>(
+        &self,
+        mut game: Game<N>,
+        network: &NET,
+        config: &SearchConfig,
+    ) {
         let mut path = vec![];
         // Perform a virtual rollout.
-        if matches!(self.virtual_rollout(&mut game, &mut path), GameResult::Ongoing) {
+        if matches!(
+            self.virtual_rollout(&mut game, &mut path, config),
+            GameResult::Ongoing
+        ) {
             // The game result isn't concrete - devirtualize the path.
-            self.devirtualize_path::<N, _>(&mut path.into_iter(), &network.policy_eval(&[game])[0]);
+            self.devirtualize_path::<N, _>(&mut path.into_iter(), &network.evaluate_batch(&[game])[0]);
         }
     }
 
     #[must_use]
     pub fn virtual_rollout<const N: usize>(
-        &mut self,
+        &self,
+        game: &mut Game<N>,
+        path: &mut Vec<usize>,
+        config: &SearchConfig,
+    ) -> GameResult {
+        self.rollout_with_action(game, path, config, None)
+    }
+
+    /// Do a virtual rollout which starts with the move at the given index,
+    /// as chosen by sequential halving.
+    #[must_use]
+    pub fn gumbel_rollout<const N: usize>(
+        &self,
+        action: usize,
         game: &mut Game<N>,
         path: &mut Vec<usize>,
+        config: &SearchConfig,
+    ) -> GameResult {
+        self.rollout_with_action(game, path, config, Some(action))
+    }
+
+    /// Do a virtual rollout, optionally forcing the first move.
+    #[must_use]
+    fn rollout_with_action<const N: usize>(
+        &self,
+        game: &mut Game<N>,
+        path: &mut Vec<usize>,
+        config: &SearchConfig,
+        action: Option<usize>,
     ) -> GameResult {
         let curr_color = game.to_move;
+        // Count the virtual visit first so that other threads avoid this path.
+        self.virtual_visits.fetch_add(1, Ordering::Relaxed);
 
-        let result = if self.is_initialized() {
-            // We've been here before - recurse if we can.
-            match self.result {
-                GameResult::Ongoing => self.select(game, path),
-                r => r,
-            }
+        let proof = self.proof();
+        let result = if proof != Proof::Unknown {
+            // The outcome is already proven - treat it like a terminal node.
+            // This also covers proven nodes which are not expanded.
+            proof.to_result(curr_color)
         } else {
             // Uninitialized node - initialize it and stop recursion.
-            self.result = game.result();
-            if self.result == GameResult::Ongoing {
-                let possible_moves = game.possible_moves();
-                let temp_policy = 1.0 / possible_moves.len() as f32;
-                self.children = possible_moves
-                    .into_iter()
-                    .map(|m| (m, Node::new(temp_policy)))
-                    .collect();
+            // Other threads arriving meanwhile wait for the expansion.
+            let mut expanded = false;
+            let expansion = self.expansion.get_or_init(|| {
+                expanded = true;
+                let expansion = Expansion::new(game, self.arena());
+                self.set_proof(Proof::from_result(expansion.result, curr_color));
+                expansion
+            });
+            match expansion.result {
+                // We've been here before - recurse if we can.
+                GameResult::Ongoing if !expanded => match action {
+                    Some(index) => self.descend(index, game, path, config),
+                    None => self.select(game, path, config),
+                },
+                GameResult::Ongoing => {
+                    // Look for short forced wins which the network might miss.
+                    let depth = if path.is_empty() {
+                        config.root_tactics_depth
+                    } else {
+                        config.tactics_depth
+                    };
+                    self.solve_tactics(game, depth);
+                    GameResult::Ongoing
+                }
+                r => r,
             }
-            self.result
         };
 
         match result {
             // Our rollout ended on a terminal node - propagate a concrete score.
             GameResult::Winner { color, .. } => {
-                self.update_concrete(if color == curr_color { -1.0 } else { 1.0 })
+                self.virtual_visits.fetch_sub(1, Ordering::Relaxed);
+                self.update_concrete(if color == curr_color {
+                    Eval::LOSS
+                } else {
+                    Eval::WIN
+                })
+            }
+            GameResult::Draw { .. } => {
+                self.virtual_visits.fetch_sub(1, Ordering::Relaxed);
+                self.update_concrete(Eval::DRAW)
             }
-            GameResult::Draw { .. } => self.update_concrete(0.0),
 
-            // We've cut the recursion short of a terminal node - count a virtual visit.
-            GameResult::Ongoing => self.virtual_visits += 1,
+            // We've cut the recursion short of a terminal node - keep the virtual visit.
+            GameResult::Ongoing => {}
         }
 
         result
     }
 
     pub fn devirtualize_path<const N: usize, I: Iterator<Item = usize>>(
-        &mut self,
+        &self,
         path: &mut I,
         net_output: &(Policy, Eval),
-    ) -> f32 {
-        self.virtual_visits -= 1;
+    ) -> Eval {
+        self.virtual_visits.fetch_sub(1, Ordering::Relaxed);
 
         let eval = if let Some(index) = path.next() {
-            self.children[index].1.devirtualize_path::<N, _>(path, net_output)
+            self.child(index).devirtualize_path::<N, _>(path, net_output)
         } else {
             let (policy, eval) = net_output;
 
             // Replace the temporary policies with the correct values.
-            self.children.iter_mut().for_each(|(mov, child)| {
-                child.policy = policy[move_index(mov, N)];
+            self.children().for_each(|(mov, edge)| {
+                edge.set_policy(policy[move_index(&mov, N)]);
             });
 
             *eval
         };
-        // Negate eval because we are switching the perspective.
-        let eval = -eval;
+        // Flip eval because we are switching the perspective.
+        let eval = eval.flip();
 
         self.update_concrete(eval);
         eval
     }
 
     #[must_use]
-    fn select<const N: usize>(&mut self, game: &mut Game<N>, path: &mut Vec<usize>) -> GameResult {
+    fn select<const N: usize>(
+        &self,
+        game: &mut Game<N>,
+        path: &mut Vec<usize>,
+        config: &SearchConfig,
+    ) -> GameResult {
+        // The path is still empty when selecting from the root.
+        if path.is_empty() {
+            if let Some(index) = self.multi_pv_target(config) {
+                return self.descend(index, game, path, config);
+            }
+        }
+
         let visit_count = self.visit_count();
-        let upper_confidence_bound = |child: &Node| -> f32 {
+        let exploration_rate = config.exploration_rate(visit_count);
+        let fpu = self.first_play_urgency(if path.is_empty() {
+            config.root_fpu
+        } else {
+            config.fpu
+        });
+        let upper_confidence_bound = |edge: EdgeRef| -> f32 {
+            let (reward, visits) = match edge.node() {
+                // Never select moves which are proven to lose.
+                Some(child) if child.proof().is_loss() => return f32::NEG_INFINITY,
+                Some(child) => (
+                    child.expected_reward_with_losses(fpu, config.virtual_loss),
+                    child.visit_count(),
+                ),
+                None => (fpu, 0.0),
+            };
             // U(s, a) = Q(s, a) + C(s) * P(s, a) * sqrt(N(s)) / (1 + N(s, a))
-            child.expected_reward_with_losses()
-                + exploration_rate(visit_count)
-                    * child.policy
-                    * (visit_count.sqrt() / (1.0 + child.visit_count()))
+            reward + exploration_rate * edge.policy() * (visit_count.sqrt() / (1.0 + visits))
         };
 
-        // Select the node to recurse into.
-        let (_ucb, (index, (my_move, node))) = self
-            .children
-            .iter_mut()
+        // Select the edge to recurse into.
+        let (_ucb, index) = self
+            .children()
             .enumerate()
-            .map(|(index, (mov, child))| (upper_confidence_bound(child), (index, (mov, child))))
+            .map(|(index, (_mov, edge))| (upper_confidence_bound(edge), index))
             .max_by(|(a, _), (b, _)| a.partial_cmp(b).expect("tried comparing nan"))
             .expect("tried to select on a node without children");
+        self.descend(index, game, path, config)
+    }
+
+    /// In MultiPV mode, find one of the top root moves which has not yet
+    /// received its guaranteed share of the visits.
+    fn multi_pv_target(&self, config: &SearchConfig) -> Option<usize> {
+        let count = config.multi_pv as usize;
+        if count <= 1 {
+            return None;
+        }
+        // Rank by visits, and by policy for moves which were not visited yet.
+        let mut ranked: Vec<usize> = (0..self.children().len())
+            .filter(|&index| !self.edge(index).proof().is_loss())
+            .collect();
+        ranked.sort_by(|&a, &b| {
+            let (a, b) = (self.edge(a), self.edge(b));
+            b.visits()
+                .cmp(&a.visits())
+                .then(b.policy().partial_cmp(&a.policy()).expect("tried comparing nan"))
+        });
+        ranked.truncate(count);
+
+        let guaranteed = self.visit_count() * config.multi_pv_share / count as f32;
+        let visit_count = |index: usize| self.edge(index).visit_count();
+        ranked
+            .into_iter()
+            .filter(|&index| visit_count(index) < guaranteed)
+            .min_by(|&a, &b| {
+                visit_count(a)
+                    .partial_cmp(&visit_count(b))
+                    .expect("tried comparing nan")
+            })
+    }
+
+    /// Continue the rollout through the child at the given index.
+    #[must_use]
+    fn descend<const N: usize>(
+        &self,
+        index: usize,
+        game: &mut Game<N>,
+        path: &mut Vec<usize>,
+        config: &SearchConfig,
+    ) -> GameResult {
+        let edge = self.edge(index);
         // Update the game state.
-        game.play(*my_move).unwrap();
+        game.play(edge.mov()).unwrap();
         // Add the move to our path.
         path.push(index);
-        // Continue the rollout.
-        node.virtual_rollout(game, path)
+        // Continue the rollout, allocating the child on its first visit.
+        let node = edge.node_or_insert();
+        let result = node.virtual_rollout(game, path, config);
+        // Resolving a child may resolve this node as well.
+        if node.proof() != Proof::Unknown {
+            self.update_proof();
+        }
+        result
     }
+}
 
-    fn update_concrete(&mut self, reward: f32) {
-        let cumulative_reward = self.expected_reward * self.visits as f32;
-        self.visits += 1;
-        self.expected_reward = (cumulative_reward + reward) / self.visits as f32;
+impl Node {
+    pub(super) fn update_concrete(&self, eval: Eval) {
+        for (total, value) in [(&self.total_reward, eval.score), (&self.total_draw, eval.draw)] {
+            total
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                    Some((f64::from_bits(total) + value as f64).to_bits())
+                })
+                .unwrap();
+        }
+        self.visits.fetch_add(1, Ordering::Relaxed);
     }
 }
@@ -1,13 +1,30 @@
+mod arena;
+mod config;
 mod debug;
+mod evaluate;
+mod filter;
+mod gumbel;
 mod mcts;
 mod move_map;
 mod node;
 mod noise;
 mod play;
+mod report;
+mod tactics;
+mod transposition;
 
+pub use arena::{Arena, NodeId, Tree};
+pub use config::{Fpu, RootSelection, SearchConfig};
 pub use debug::{MoveInfo, NodeDebugInfo};
-pub use move_map::{move_index, possible_patterns};
-pub use node::Node;
+pub use evaluate::{evaluate, SearchResult};
+pub use filter::MoveFilter;
+pub use gumbel::GumbelRoot;
+pub use move_map::{move_from_index, move_index, possible_patterns};
+pub use node::{EdgeRef, Node, NodeRef, Proof};
+pub use report::{PvEntry, SearchReport};
+pub use tactics::forced_win;
+pub(crate) use transposition::CompactOutput;
+pub use transposition::{position_key, PositionKey};
 
 #[cfg(test)]
 mod tests;
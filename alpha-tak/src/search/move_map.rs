@@ -10,6 +10,7 @@ lazy_static! {
         }
         map
     };
+    static ref INDEX_LUT: Vec<Vec<Option<Move>>> = (0..=8).map(build_index_lut).collect();
 }
 
 pub const fn possible_patterns(board_size: usize) -> usize {
@@ -47,6 +48,64 @@ pub fn move_index(m: &Move, board_size: usize) -> usize {
     }
 }
 
+/// Inverse of `move_index`.
+/// Returns `None` if the index does not correspond to any move.
+pub fn move_from_index(index: usize, board_size: usize) -> Option<Move> {
+    assert!(board_size <= 8);
+    if board_size == 5 {
+        POSSIBLE_MOVES_IN_5S.get(index).map(|m| m.parse().unwrap())
+    } else {
+        INDEX_LUT[board_size].get(index).copied().flatten()
+    }
+}
+
+/// Enumerate every move that has an index, including spreads
+/// which would leave the board, and record them in index order.
+fn build_index_lut(board_size: usize) -> Vec<Option<Move>> {
+    if !(3..=8).contains(&board_size) || board_size == 5 {
+        return Vec::new();
+    }
+    let mut patterns = Vec::new();
+    drop_patterns(board_size, Vec::new(), &mut patterns);
+
+    let mut lut = vec![None; board_size * board_size * (3 + 4 * possible_patterns(board_size))];
+    for x in 0..board_size {
+        for y in 0..board_size {
+            let square = Square::new(x as u8, y as u8);
+            let places = [Piece::Flat, Piece::Wall, Piece::Cap].map(MoveKind::Place);
+            let spreads = [Direction::Up, Direction::Down, Direction::Left, Direction::Right]
+                .into_iter()
+                .flat_map(|direction| {
+                    patterns
+                        .iter()
+                        .map(move |drops| MoveKind::Spread(direction, drops.iter().copied().collect()))
+                });
+            for kind in places.into_iter().chain(spreads) {
+                let m = Move::new(square, kind);
+                lut[move_index(&m, board_size)] = Some(m);
+            }
+        }
+    }
+    lut
+}
+
+/// Collect all drop sequences that carry at most `board_size` pieces
+/// over at most `board_size - 1` squares.
+fn drop_patterns(board_size: usize, drops: Vec<u32>, out: &mut Vec<Vec<u32>>) {
+    let carried = drops.iter().sum::<u32>() as usize;
+    if !drops.is_empty() {
+        out.push(drops.clone());
+    }
+    if drops.len() + 1 >= board_size {
+        return;
+    }
+    for drop in 1..=(board_size - carried) as u32 {
+        let mut next = drops.clone();
+        next.push(drop);
+        drop_patterns(board_size, next, out);
+    }
+}
+
 // Needed for backwards compatibility with the already trained WilemBot for 5s.
 const POSSIBLE_MOVES_IN_5S: [&str; 1575] = [
     "a1", "Sa1", "Ca1", "a2", "Sa2", "Ca2", "a3", "Sa3", "Ca3", "a4", "Sa4", "Ca4", "a5", "Sa5", "Ca5", "b1",
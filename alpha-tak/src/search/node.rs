@@ -1,39 +1,420 @@
+use std::{
+    fmt::Display,
+    ops::{Deref, Not},
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
 use tak::*;
 
-#[derive(Clone, Debug, Default)]
-pub struct Node {
-    pub policy: f32,
-    pub expected_reward: f32,
+use super::{
+    arena::{Arena, NodeId},
+    config::Fpu,
+};
+
+/// Game theoretic value of a node, from the perspective of the player who
+/// made the move leading to it (the same perspective as the expected reward).
+/// Wins and losses hold the number of plies until the game ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Proof {
+    #[default]
+    Unknown,
+    Win(u16),
+    Loss(u16),
+    Draw,
+}
+
+impl Proof {
+    /// Get the proof for a terminal position.
+    pub fn from_result(result: GameResult, to_move: Color) -> Self {
+        match result {
+            GameResult::Winner { color, .. } if color == to_move => Proof::Loss(0),
+            GameResult::Winner { .. } => Proof::Win(0),
+            GameResult::Draw { .. } => Proof::Draw,
+            GameResult::Ongoing => Proof::Unknown,
+        }
+    }
+
+    /// Get a game result which matches the proof.
+    pub fn to_result(self, to_move: Color) -> GameResult {
+        match self {
+            Proof::Win(_) => GameResult::Winner {
+                color: to_move.not(),
+                road: false,
+            },
+            Proof::Loss(_) => GameResult::Winner {
+                color: to_move,
+                road: false,
+            },
+            Proof::Draw => GameResult::Draw {
+                reversible_plies: false,
+            },
+            Proof::Unknown => GameResult::Ongoing,
+        }
+    }
+
+    /// Get the proof from the perspective of the other player.
+    pub fn flip(self) -> Self {
+        match self {
+            Proof::Win(n) => Proof::Loss(n),
+            Proof::Loss(n) => Proof::Win(n),
+            proof => proof,
+        }
+    }
+
+    pub fn is_loss(self) -> bool {
+        matches!(self, Proof::Loss(_))
+    }
+
+    // Pack the proof into an integer so that it can be stored atomically.
+    fn to_bits(self) -> u32 {
+        match self {
+            Proof::Unknown => 0,
+            Proof::Draw => 1,
+            Proof::Win(n) => 2 | (n as u32) << 2,
+            Proof::Loss(n) => 3 | (n as u32) << 2,
+        }
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        let n = (bits >> 2) as u16;
+        match bits & 3 {
+            0 => Proof::Unknown,
+            1 => Proof::Draw,
+            2 => Proof::Win(n),
+            _ => Proof::Loss(n),
+        }
+    }
+}
+
+impl Display for Proof {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Proof::Unknown => Ok(()),
+            Proof::Win(n) => write!(f, "win in {n}"),
+            Proof::Loss(n) => write!(f, "loss in {n}"),
+            Proof::Draw => write!(f, "draw"),
+        }
+    }
+}
+
+// Child of an edge which has not been visited yet.
+const NO_NODE: NodeId = NodeId::MAX;
+
+/// A move from a node together with its prior.
+/// The child node is only allocated once the move is selected,
+/// so unexplored moves stay small.
+#[derive(Debug)]
+pub(super) struct Edge {
+    pub mov: Move,
+    pub policy: AtomicU32,
+    pub child: AtomicU32,
+}
+
+impl Edge {
+    pub fn new(mov: Move, policy: f32) -> Self {
+        Edge {
+            mov,
+            policy: AtomicU32::new(policy.to_bits()),
+            child: AtomicU32::new(NO_NODE),
+        }
+    }
+
+    /// Get the child node if it has been allocated.
+    pub fn child(&self) -> Option<NodeId> {
+        Some(self.child.load(Ordering::Acquire)).filter(|&id| id != NO_NODE)
+    }
+}
+
+/// Result and children of a node, which are set once on the first visit.
+#[derive(Debug, Default)]
+pub(super) struct Expansion {
     pub result: GameResult,
-    pub visits: u32,
-    pub virtual_visits: u32,
-    pub children: Box<[(Move, Node)]>,
+    pub children: Box<[Edge]>,
+}
+
+impl Expansion {
+    pub fn new<const N: usize>(game: &Game<N>, arena: &Arena) -> Self {
+        let result = game.result();
+        let children: Box<[Edge]> = if result == GameResult::Ongoing {
+            let possible_moves = game.possible_moves();
+            let temp_policy = 1.0 / possible_moves.len() as f32;
+            possible_moves
+                .into_iter()
+                .map(|m| Edge::new(m, temp_policy))
+                .collect()
+        } else {
+            Box::default()
+        };
+        arena.add_edges(children.len());
+        Expansion { result, children }
+    }
+}
+
+/// A node of the search tree. The statistics are atomic so that several
+/// threads can descend the tree at once. Threads only wait for each other
+/// while a node is being expanded.
+#[derive(Debug, Default)]
+pub struct Node {
+    pub(super) total_reward: AtomicU64,
+    // Sum of the predicted draw probabilities.
+    pub(super) total_draw: AtomicU64,
+    pub(super) visits: AtomicU32,
+    pub(super) virtual_visits: AtomicU32,
+    pub(super) proof: AtomicU32,
+    pub(super) expansion: OnceLock<Expansion>,
 }
 
 impl Node {
-    pub fn new(policy: f32) -> Self {
-        Node {
-            policy,
-            ..Default::default()
+    pub fn visits(&self) -> u32 {
+        self.visits.load(Ordering::Relaxed)
+    }
+
+    pub fn virtual_visits(&self) -> u32 {
+        self.virtual_visits.load(Ordering::Relaxed)
+    }
+
+    pub fn proof(&self) -> Proof {
+        Proof::from_bits(self.proof.load(Ordering::Relaxed))
+    }
+
+    pub(super) fn set_proof(&self, proof: Proof) {
+        self.proof.store(proof.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Get the average reward from the perspective of the player who moved
+    /// into this node.
+    pub fn expected_reward(&self) -> f32 {
+        let visits = self.visits();
+        if visits == 0 {
+            0.0
+        } else {
+            (f64::from_bits(self.total_reward.load(Ordering::Relaxed)) / visits as f64) as f32
+        }
+    }
+
+    /// Get the average predicted probability of a draw.
+    pub fn draw_probability(&self) -> f32 {
+        let visits = self.visits();
+        if visits == 0 {
+            0.0
+        } else {
+            (f64::from_bits(self.total_draw.load(Ordering::Relaxed)) / visits as f64) as f32
         }
     }
 
+    /// Get the game result at this node, which is ongoing until it is expanded.
+    pub fn result(&self) -> GameResult {
+        self.expansion
+            .get()
+            .map(|expansion| expansion.result)
+            .unwrap_or_default()
+    }
+
     /// Check whether this node has been visited at least once
     /// and that the children are initialized.
     pub fn is_initialized(&self) -> bool {
-        self.visits != 0 || self.virtual_visits != 0
+        self.expansion.get().is_some()
     }
 
     /// Get the visit count of this node, including virtual visits.
     pub fn visit_count(&self) -> f32 {
-        (self.visits + self.virtual_visits) as f32
+        (self.visits() + self.virtual_visits()) as f32
     }
 
     /// Get the expected reward, accounting for virtual losses.
-    pub fn expected_reward_with_losses(&self) -> f32 {
-        if !self.is_initialized() {
-            return 0.0;
+    /// Unvisited nodes use the given first play urgency.
+    pub fn expected_reward_with_losses(&self, fpu: f32, virtual_loss: f32) -> f32 {
+        let visit_count = self.visit_count();
+        if visit_count == 0.0 {
+            return fpu;
+        }
+        (self.expected_reward() * self.visits() as f32 - virtual_loss * self.virtual_visits() as f32)
+            / visit_count
+    }
+}
+
+/// A node together with the arena which holds its children.
+#[derive(Clone, Copy)]
+pub struct NodeRef<'a> {
+    arena: &'a Arena,
+    id: NodeId,
+    node: &'a Node,
+}
+
+impl<'a> Deref for NodeRef<'a> {
+    type Target = Node;
+
+    fn deref(&self) -> &Node {
+        self.node
+    }
+}
+
+impl<'a> NodeRef<'a> {
+    pub(super) fn new(arena: &'a Arena, id: NodeId) -> Self {
+        NodeRef {
+            arena,
+            id,
+            node: arena.get(id),
         }
-        (self.expected_reward * self.visits as f32 - self.virtual_visits as f32) / self.visit_count()
+    }
+
+    pub(super) fn id(self) -> NodeId {
+        self.id
+    }
+
+    /// Get the moves from this node, which are empty until it is expanded.
+    pub fn children(self) -> impl ExactSizeIterator<Item = (Move, EdgeRef<'a>)> + Clone {
+        let children = self
+            .node
+            .expansion
+            .get()
+            .map(|expansion| &*expansion.children)
+            .unwrap_or_default();
+        children.iter().map(move |edge| {
+            (edge.mov, EdgeRef {
+                arena: self.arena,
+                edge,
+            })
+        })
+    }
+
+    /// Get the move at the given index.
+    pub fn edge(self, index: usize) -> EdgeRef<'a> {
+        let expansion = self.node.expansion.get().expect("node must be initialized");
+        EdgeRef {
+            arena: self.arena,
+            edge: &expansion.children[index],
+        }
+    }
+
+    /// Get the child at the given index, which must have been visited.
+    pub fn child(self, index: usize) -> NodeRef<'a> {
+        self.edge(index)
+            .node()
+            .expect("path leads to an unallocated node")
+    }
+
+    pub(super) fn arena(self) -> &'a Arena {
+        self.arena
+    }
+
+    /// Derive the proof of this node from the proofs of its children.
+    /// A single winning move proves a loss for the previous player,
+    /// and only if every move loses is it a win.
+    pub(super) fn update_proof(&self) {
+        let mut shortest_win = None;
+        let mut longest_loss = 0;
+        let mut any_draw = false;
+        let mut all_proven = true;
+        for (_mov, edge) in self.children() {
+            match edge.proof() {
+                Proof::Win(n) => shortest_win = Some(shortest_win.map_or(n, |w: u16| w.min(n))),
+                Proof::Loss(n) => longest_loss = longest_loss.max(n),
+                Proof::Draw => any_draw = true,
+                Proof::Unknown => all_proven = false,
+            }
+        }
+        let proof = if let Some(n) = shortest_win {
+            Proof::Loss(n + 1)
+        } else if !all_proven || self.children().len() == 0 {
+            // Proofs are only ever added, so there is nothing to store.
+            return;
+        } else if any_draw {
+            Proof::Draw
+        } else {
+            Proof::Win(longest_loss + 1)
+        };
+        self.set_proof(proof);
+    }
+
+    /// Get the expected reward to use for unvisited children of this node.
+    pub fn first_play_urgency(&self, fpu: Fpu) -> f32 {
+        match fpu {
+            Fpu::Absolute(value) => value,
+            Fpu::Reduction(reduction) => {
+                // The expected reward is stored from the perspective of the previous player.
+                let parent_reward = if self.visits() > 0 {
+                    -self.expected_reward()
+                } else {
+                    0.0
+                };
+                let visited_policy: f32 = self
+                    .children()
+                    .filter(|(_, edge)| edge.visit_count() > 0.0)
+                    .map(|(_, edge)| edge.policy())
+                    .sum();
+                (parent_reward - reduction * visited_policy.sqrt()).clamp(-1.0, 1.0)
+            }
+        }
+    }
+}
+
+/// An edge of the search tree, giving access to its child if it exists.
+/// Unvisited moves report no visits and an unknown proof.
+#[derive(Clone, Copy)]
+pub struct EdgeRef<'a> {
+    arena: &'a Arena,
+    edge: &'a Edge,
+}
+
+impl<'a> EdgeRef<'a> {
+    pub fn mov(self) -> Move {
+        self.edge.mov
+    }
+
+    pub fn policy(self) -> f32 {
+        f32::from_bits(self.edge.policy.load(Ordering::Relaxed))
+    }
+
+    pub(super) fn set_policy(self, policy: f32) {
+        self.edge.policy.store(policy.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Get the child node if the move has been visited.
+    pub fn node(self) -> Option<NodeRef<'a>> {
+        self.edge.child().map(|id| NodeRef::new(self.arena, id))
+    }
+
+    /// Get the child node, allocating it on the first visit.
+    pub(super) fn node_or_insert(self) -> NodeRef<'a> {
+        if let Some(node) = self.node() {
+            return node;
+        }
+        let id = self.arena.alloc();
+        match self
+            .edge
+            .child
+            .compare_exchange(NO_NODE, id, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => NodeRef::new(self.arena, id),
+            Err(existing) => {
+                // Another thread allocated the child first.
+                self.arena.release(id);
+                NodeRef::new(self.arena, existing)
+            }
+        }
+    }
+
+    pub fn visits(self) -> u32 {
+        self.node().map_or(0, |node| node.visits())
+    }
+
+    pub fn visit_count(self) -> f32 {
+        self.node().map_or(0.0, |node| node.visit_count())
+    }
+
+    pub fn expected_reward(self) -> f32 {
+        self.node().map_or(0.0, |node| node.expected_reward())
+    }
+
+    pub fn draw_probability(self) -> f32 {
+        self.node().map_or(0.0, |node| node.draw_probability())
+    }
+
+    pub fn proof(self) -> Proof {
+        self.node().map_or(Proof::Unknown, |node| node.proof())
     }
 }
@@ -1,17 +1,22 @@
 use rand_distr::{Dirichlet, Distribution};
 
-use super::node::Node;
+use super::node::NodeRef;
 
-impl Node {
-    pub fn apply_dirichlet(&mut self, alpha: f32, ratio: f32) {
+impl NodeRef<'_> {
+    pub fn apply_dirichlet(&self, alpha: f32, ratio: f32) {
         assert!(
-            self.visits > 0,
+            self.visits() > 0,
             "cannot apply dirichlet noise without initialized policy"
         );
-        let dirichlet = Dirichlet::new(&vec![alpha; self.children.len()]).unwrap();
+        // The noise is a distribution over the moves, so a single move
+        // is left as it is.
+        if self.children().len() < 2 {
+            return;
+        }
+        let dirichlet = Dirichlet::new(&vec![alpha; self.children().len()]).unwrap();
         let samples = dirichlet.sample(&mut rand::thread_rng());
-        for ((_move, node), noise) in self.children.iter_mut().zip(samples) {
-            node.policy = noise * ratio + node.policy * (1. - ratio);
+        for ((_move, edge), noise) in self.children().zip(samples) {
+            edge.set_policy(noise * ratio + edge.policy() * (1. - ratio));
         }
     }
 }
@@ -2,9 +2,9 @@ use rand::thread_rng;
 use rand_distr::{Distribution, WeightedIndex};
 use tak::*;
 
-use super::node::Node;
+use super::node::{NodeRef, Proof};
 
-impl Node {
+impl NodeRef<'_> {
     fn check_initialized(&self) {
         assert!(self.is_initialized(), "node must be initialized");
     }
@@ -14,42 +14,67 @@ impl Node {
         self.check_initialized();
         // After many rollouts the visit counts become a better
         // estimate for policy (not normalized).
-        self.children
-            .iter()
-            .map(|(mov, node)| (*mov, node.visits))
-            .collect()
+        self.children().map(|(mov, edge)| (mov, edge.visits())).collect()
     }
 
-    /// Get the sub-tree for the given move.
-    /// This allows tree reuse.
-    #[must_use]
-    pub fn play(mut self, my_move: Move) -> Node {
-        self.check_initialized();
-
-        let index = self
-            .children
-            .iter()
-            .enumerate()
-            .find(|(_i, (mov, _node))| mov == &my_move)
-            .expect("tried to play an invalid move")
-            .0;
-        let child = self.children.get_mut(index).map(|(_, node)| node).unwrap();
-        let child = std::mem::take(child);
-
-        // Drop other children on a separate thread.
-        std::thread::spawn(move || drop(self));
-
-        child
+    /// Select a move to play.
+    /// With a temperature of zero, it will return the move with the most
+    /// visits. Otherwise, it will return a random move weighted by the
+    /// number of visits raised to the power of `1 / temperature`.
+    /// Proven wins are always played, and proven losses are avoided
+    /// unless every move loses.
+    pub fn pick_move(&self, temperature: f32) -> Move {
+        self.pick_move_pruned(temperature, 0.0)
     }
 
-    /// Select a move to play.
-    /// When exploitation is true, it will return the move with the most visits.
-    /// If exploitation is false, it will return a random move weighted by the
-    /// number of visits.
-    pub fn pick_move(&self, exploitation: bool) -> Move {
+    /// Select a move to play like [`NodeRef::pick_move`], but never sample
+    /// moves with fewer visits than `min_visit_ratio` times the visits of the
+    /// most visited move.
+    pub fn pick_move_pruned(&self, temperature: f32, min_visit_ratio: f32) -> Move {
         let improved_policy = self.improved_policy();
 
-        if exploitation {
+        // Play the fastest proven win.
+        let fastest_win = self
+            .children()
+            .filter_map(|(mov, edge)| match edge.proof() {
+                Proof::Win(plies) => Some((mov, plies)),
+                _ => None,
+            })
+            .min_by_key(|(_, plies)| *plies);
+        if let Some((my_move, _)) = fastest_win {
+            return my_move;
+        }
+
+        if self.children().all(|(_, edge)| edge.proof().is_loss()) {
+            // Every move loses, so delay the loss for as long as possible.
+            return self
+                .children()
+                .max_by_key(|(_, edge)| match edge.proof() {
+                    Proof::Loss(plies) => plies,
+                    _ => 0,
+                })
+                .unwrap()
+                .0;
+        }
+        let improved_policy: Vec<_> = improved_policy
+            .into_iter()
+            .zip(self.children())
+            .filter(|(_, (_, edge))| !edge.proof().is_loss())
+            .map(|(entry, _)| entry)
+            .collect();
+        // Drop rarely visited moves, but always keep the most visited one.
+        let most_visits = improved_policy
+            .iter()
+            .map(|(_, visits)| *visits)
+            .max()
+            .unwrap_or_default();
+        let min_visits = most_visits as f32 * min_visit_ratio.min(1.0);
+        let improved_policy: Vec<_> = improved_policy
+            .into_iter()
+            .filter(|(_, visits)| *visits as f32 >= min_visits)
+            .collect();
+
+        if temperature <= 0.0 {
             // When exploiting always pick the move with the most visits.
             improved_policy
                 .into_iter()
@@ -58,11 +83,43 @@ impl Node {
                 .0
         } else {
             // Split into moves and weights.
-            let (mut moves, weights): (Vec<_>, Vec<_>) = improved_policy.into_iter().unzip();
+            let (mut moves, visits): (Vec<_>, Vec<_>) = improved_policy.into_iter().unzip();
+            // Normalize before exponentiating to avoid overflow at low temperatures.
+            let max = visits.iter().copied().max().unwrap_or_default().max(1) as f32;
+            let weights: Vec<_> = visits
+                .into_iter()
+                .map(|v| (v as f32 / max).powf(1.0 / temperature))
+                .collect();
             // Randomly pick based on weights from the improved policy.
-            let distr = WeightedIndex::new(&weights).unwrap();
-            let index = distr.sample(&mut thread_rng());
+            // Fall back to the first move if none of them were visited.
+            let index = WeightedIndex::new(&weights)
+                .map(|distr| distr.sample(&mut thread_rng()))
+                .unwrap_or_default();
             moves.swap_remove(index)
         }
     }
+
+    /// Check whether the most visited move can no longer be overtaken
+    /// within the given number of additional visits,
+    /// or whether there is only one legal move.
+    pub fn is_decided(&self, visits_left: u32) -> bool {
+        if !self.is_initialized() {
+            return false;
+        }
+        // The outcome is already known.
+        if self.proof() != Proof::Unknown {
+            return true;
+        }
+        let (mut best, mut second) = (0, 0);
+        for (_mov, edge) in self.children() {
+            let visits = edge.visits();
+            if visits > best {
+                second = best;
+                best = visits;
+            } else if visits > second {
+                second = visits;
+            }
+        }
+        self.children().len() <= 1 || second.saturating_add(visits_left) < best
+    }
 }
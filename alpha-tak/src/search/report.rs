@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use tak::*;
+
+use super::node::{NodeRef, Proof};
+use crate::model::score::{centiflats, win_probability, DEFAULT_LOGIT_PER_FLAT};
+
+/// One ply of the principal variation.
+/// The reward is from the perspective of the player making the move.
+#[derive(Clone, Debug)]
+pub struct PvEntry {
+    pub mov: Move,
+    pub visits: u32,
+    pub reward: f32,
+    pub proof: Proof,
+}
+
+/// Statistics about a search, meant for front-ends and logs.
+#[derive(Clone, Debug, Default)]
+pub struct SearchReport {
+    /// Visits of the root, including visits reused from earlier searches.
+    pub nodes: u32,
+    /// Visits added since the search of this position started.
+    pub nodes_searched: u32,
+    pub time: Duration,
+    /// Expected reward for the player to move.
+    pub eval: f32,
+    /// Score in centiflats for the player to move.
+    pub score: i32,
+    /// Number of visited nodes at each depth, starting with the root.
+    pub depth_distribution: Vec<u32>,
+    pub pv: Vec<PvEntry>,
+    /// Principal variations starting with each of the most visited root moves.
+    pub lines: Vec<Vec<PvEntry>>,
+    pub cache_hit_rate: Option<f64>,
+}
+
+impl SearchReport {
+    /// Create a report from the tree, without timing or cache statistics.
+    pub fn from_node(node: NodeRef, max_pv_length: usize) -> Self {
+        let mut depth_distribution = Vec::new();
+        count_depths(node, 0, &mut depth_distribution);
+        SearchReport {
+            nodes: node.visits(),
+            nodes_searched: node.visits(),
+            // The node stores values from the perspective of the previous player.
+            eval: -node.expected_reward(),
+            score: centiflats(-node.expected_reward(), DEFAULT_LOGIT_PER_FLAT),
+            depth_distribution,
+            pv: principal_variation(node, max_pv_length),
+            ..Default::default()
+        }
+    }
+
+    /// Add a principal variation for each of the `count` most visited moves.
+    #[must_use]
+    pub fn with_lines(mut self, node: NodeRef, count: usize, max_length: usize) -> Self {
+        let mut edges: Vec<_> = node.children().collect();
+        edges.sort_by_key(|(_mov, edge)| std::cmp::Reverse(edge.visits()));
+        self.lines = edges
+            .into_iter()
+            .take(count)
+            .filter_map(|(mov, edge)| {
+                let child = edge.node().filter(|child| child.visits() > 0)?;
+                let mut line = vec![PvEntry {
+                    mov,
+                    visits: child.visits(),
+                    reward: child.expected_reward(),
+                    proof: child.proof(),
+                }];
+                line.extend(principal_variation(child, max_length.saturating_sub(1)));
+                Some(line)
+            })
+            .collect();
+        self
+    }
+
+    /// Convert the evaluation to centiflats with a calibrated scale.
+    #[must_use]
+    pub fn with_logit_per_flat(mut self, logit_per_flat: f32) -> Self {
+        self.score = centiflats(self.eval, logit_per_flat);
+        self
+    }
+
+    /// Add the hit rate of a network evaluation cache.
+    #[must_use]
+    pub fn with_cache_hit_rate(mut self, hit_rate: f64) -> Self {
+        self.cache_hit_rate = Some(hit_rate);
+        self
+    }
+
+    /// Nodes searched per second.
+    pub fn nps(&self) -> f64 {
+        if self.time.is_zero() {
+            0.0
+        } else {
+            self.nodes_searched as f64 / self.time.as_secs_f64()
+        }
+    }
+
+    /// Average depth of visited nodes.
+    pub fn average_depth(&self) -> f32 {
+        let (count, total) = self
+            .depth_distribution
+            .iter()
+            .enumerate()
+            .fold((0, 0), |(count, total), (depth, &nodes)| {
+                (count + nodes as u64, total + depth as u64 * nodes as u64)
+            });
+        if count == 0 {
+            0.0
+        } else {
+            total as f32 / count as f32
+        }
+    }
+
+    /// Depth of the deepest visited node.
+    pub fn selective_depth(&self) -> usize {
+        self.depth_distribution.len().saturating_sub(1)
+    }
+
+    /// Serialize the report as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        fn line(entries: &[PvEntry]) -> String {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|entry| {
+                    let proof = if entry.proof == Proof::Unknown {
+                        "null".to_string()
+                    } else {
+                        format!("\"{}\"", entry.proof)
+                    };
+                    format!(
+                        "{{\"move\":\"{}\",\"visits\":{},\"reward\":{},\"proof\":{}}}",
+                        entry.mov,
+                        entry.visits,
+                        json_number(entry.reward as f64, 4),
+                        proof
+                    )
+                })
+                .collect();
+            format!("[{}]", entries.join(","))
+        }
+
+        let lines: Vec<_> = self.lines.iter().map(|entries| line(entries)).collect();
+        let depth_distribution = self
+            .depth_distribution
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let fields = [
+            ("nodes", self.nodes.to_string()),
+            ("nodes_searched", self.nodes_searched.to_string()),
+            ("time_ms", self.time.as_millis().to_string()),
+            ("nps", json_number(self.nps(), 1)),
+            ("eval", json_number(self.eval as f64, 4)),
+            (
+                "win_probability",
+                json_number(win_probability(self.eval) as f64, 4),
+            ),
+            ("score_centiflats", self.score.to_string()),
+            ("average_depth", json_number(self.average_depth() as f64, 2)),
+            ("selective_depth", self.selective_depth().to_string()),
+            ("depth_distribution", format!("[{depth_distribution}]")),
+            ("pv", line(&self.pv)),
+            ("lines", format!("[{}]", lines.join(","))),
+            (
+                "cache_hit_rate",
+                self.cache_hit_rate
+                    .map_or_else(|| "null".to_string(), |rate| json_number(rate, 4)),
+            ),
+        ];
+        let fields: Vec<_> = fields
+            .iter()
+            .map(|(key, value)| format!("\"{key}\":{value}"))
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+/// Format a number for JSON, which has no representation for NaN or infinity.
+fn json_number(value: f64, precision: usize) -> String {
+    if value.is_finite() {
+        format!("{value:.precision$}")
+    } else {
+        "null".to_string()
+    }
+}
+
+fn count_depths(node: NodeRef, depth: usize, distribution: &mut Vec<u32>) {
+    if node.visits() == 0 {
+        return;
+    }
+    if distribution.len() <= depth {
+        distribution.push(0);
+    }
+    distribution[depth] += 1;
+    for child in node.children().filter_map(|(_mov, edge)| edge.node()) {
+        count_depths(child, depth + 1, distribution);
+    }
+}
+
+pub(crate) fn principal_variation(node: NodeRef, max_length: usize) -> Vec<PvEntry> {
+    let mut pv = Vec::new();
+    let mut node = node;
+    while pv.len() < max_length && node.is_initialized() && node.children().len() > 0 {
+        let my_move = node.pick_move(0.0);
+        let edge = node.children().find(|(mov, _edge)| *mov == my_move).unwrap().1;
+        let child = match edge.node() {
+            Some(child) if child.visits() > 0 => child,
+            _ => break,
+        };
+        pv.push(PvEntry {
+            mov: my_move,
+            visits: child.visits(),
+            reward: child.expected_reward(),
+            proof: child.proof(),
+        });
+        node = child;
+    }
+    pv
+}
@@ -0,0 +1,91 @@
+use std::ops::Not;
+
+use tak::*;
+
+use super::node::{NodeRef, Proof};
+
+fn wins<const N: usize>(game: &Game<N>, color: Color) -> bool {
+    matches!(game.result(), GameResult::Winner { color: winner, .. } if winner == color)
+}
+
+/// Pass the turn to the other player, to see what they threaten.
+fn null_move<const N: usize>(game: &Game<N>) -> Game<N> {
+    let mut game = game.clone();
+    game.to_move = game.to_move.not();
+    game.ply += 1;
+    game
+}
+
+fn immediate_win<const N: usize>(game: &Game<N>) -> Option<Move> {
+    game.possible_moves().into_iter().find(|&my_move| {
+        let mut clone = game.clone();
+        clone.play(my_move).unwrap();
+        wins(&clone, game.to_move)
+    })
+}
+
+/// Search for a win for the player to move within `depth` plies.
+/// Returns the first move and the number of plies until the game is won.
+///
+/// Beyond the first ply, only moves which threaten an immediate win are
+/// tried, and every reply of the opponent is checked.
+pub fn forced_win<const N: usize>(game: &Game<N>, depth: u16) -> Option<(Move, u16)> {
+    if depth == 0 || game.result() != GameResult::Ongoing {
+        return None;
+    }
+    if let Some(my_move) = immediate_win(game) {
+        return Some((my_move, 1));
+    }
+    if depth < 3 {
+        return None;
+    }
+
+    let attacker = game.to_move;
+    'threats: for my_move in game.possible_moves() {
+        let mut after = game.clone();
+        after.play(my_move).unwrap();
+        // Immediate wins were handled above, so this move ends in a loss or draw.
+        if after.result() != GameResult::Ongoing {
+            continue;
+        }
+        // Only consider moves that create a threat.
+        if immediate_win(&null_move(&after)).is_none() {
+            continue;
+        }
+
+        let mut longest = 0;
+        for reply in after.possible_moves() {
+            let mut defended = after.clone();
+            defended.play(reply).unwrap();
+            let plies = match defended.result() {
+                // The opponent completed our road for us.
+                GameResult::Winner { color, .. } if color == attacker => 0,
+                GameResult::Ongoing => match forced_win(&defended, depth - 2) {
+                    Some((_, plies)) => plies,
+                    None => continue 'threats,
+                },
+                _ => continue 'threats,
+            };
+            longest = longest.max(plies);
+        }
+        return Some((my_move, longest + 2));
+    }
+    None
+}
+
+impl NodeRef<'_> {
+    /// Look for a forced win for the player to move and record it
+    /// in the proofs of this node and the winning child.
+    /// The node must already have its children.
+    pub fn solve_tactics<const N: usize>(&self, game: &Game<N>, depth: u16) {
+        if depth == 0 || self.children().len() == 0 || self.proof() != Proof::Unknown {
+            return;
+        }
+        if let Some((my_move, plies)) = forced_win(game, depth) {
+            if let Some((_, edge)) = self.children().find(|(mov, _)| mov == &my_move) {
+                edge.node_or_insert().set_proof(Proof::Win(plies - 1));
+                self.update_proof();
+            }
+        }
+    }
+}
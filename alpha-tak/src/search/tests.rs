@@ -1,11 +1,40 @@
+use std::{
+    sync::{atomic::Ordering, Barrier},
+    thread::{self, spawn},
+    time::Duration,
+};
+
 use tak::*;
 
-use super::node::Node;
-use crate::{model::network::Network, repr::output_size};
+use super::{
+    config::{Fpu, RootSelection, SearchConfig},
+    evaluate::evaluate,
+    filter::MoveFilter,
+    gumbel::GumbelRoot,
+    move_map::{move_from_index, move_index},
+    node::{Edge, Expansion, NodeRef, Proof},
+    position_key,
+    report::SearchReport,
+    tactics::forced_win,
+    Tree,
+};
+use crate::{
+    model::{
+        cache::CachedNetwork,
+        inference::inference_channel,
+        network::{Eval, Evaluator, Heads, Network, Policy},
+    },
+    player::Player,
+    repr::output_size,
+};
 
 #[derive(Default)]
 struct DummyNet {}
 impl<const N: usize> Network<N> for DummyNet {
+    fn with_heads(_heads: Heads) -> Self {
+        unimplemented!()
+    }
+
     fn vs(&self) -> &tch::nn::VarStore {
         unimplemented!()
     }
@@ -30,19 +59,43 @@ impl<const N: usize> Network<N> for DummyNet {
         &self,
         games: &[Game<N>],
     ) -> Vec<(crate::model::network::Policy, crate::model::network::Eval)> {
-        vec![(vec![1.0; output_size(N)], 0.0); games.len()]
+        vec![(vec![1.0; output_size(N)], Default::default()); games.len()]
     }
 }
 
+/// Expand the root by hand with children of the given visits and proofs.
+fn expand_root(tree: &Tree, children: &[(&str, u32, Proof)]) {
+    let arena = tree.arena();
+    let children = children
+        .iter()
+        .map(|&(mov, visits, proof)| {
+            let child = arena.alloc();
+            arena.get(child).visits.store(visits, Ordering::Relaxed);
+            arena.get(child).set_proof(proof);
+            let edge = Edge::new(mov.parse().unwrap(), 0.0);
+            edge.child.store(child, Ordering::Relaxed);
+            edge
+        })
+        .collect();
+    tree.root()
+        .expansion
+        .set(Expansion {
+            result: GameResult::Ongoing,
+            children,
+        })
+        .unwrap();
+}
+
 #[test]
 fn win_in_one() {
     let mut game = Game::<3>::from_ptn_moves(&["a3", "c3", "c2", "a2"]).unwrap();
-    let mut node = Node::default();
+    let tree = Tree::default();
 
     for _ in 0..1000 {
-        node.rollout(game.clone(), &DummyNet {})
+        tree.root()
+            .rollout(game.clone(), &DummyNet {}, &SearchConfig::default())
     }
-    game.play(node.pick_move(true)).unwrap();
+    game.play(tree.root().pick_move(0.0)).unwrap();
     assert_eq!(game.result(), GameResult::Winner {
         color: Color::White,
         road: true
@@ -52,21 +105,604 @@ fn win_in_one() {
 #[test]
 fn prevent_win_in_two() {
     let mut game = Game::<3>::from_ptn_moves(&["a3", "c3", "c2"]).unwrap();
-    let mut node = Node::default();
+    let mut tree = Tree::default();
 
     // Black move.
     for _ in 0..1000 {
-        node.rollout(game.clone(), &DummyNet {});
+        tree.root()
+            .rollout(game.clone(), &DummyNet {}, &SearchConfig::default());
     }
-    let my_move = node.pick_move(true);
-    node = node.play(my_move);
+    let my_move = tree.root().pick_move(0.0);
+    tree.play(my_move);
     game.play(my_move).unwrap();
     assert_eq!(game.result(), GameResult::Ongoing);
 
     // White move.
     for _ in 0..1000 {
-        node.rollout(game.clone(), &DummyNet {});
+        tree.root()
+            .rollout(game.clone(), &DummyNet {}, &SearchConfig::default());
     }
-    game.play(node.pick_move(true)).unwrap();
+    game.play(tree.root().pick_move(0.0)).unwrap();
     assert_eq!(game.result(), GameResult::Ongoing);
 }
+
+#[test]
+fn move_index_round_trip() {
+    fn check<const N: usize>(ptn: &[&str]) {
+        let game = Game::<N>::from_ptn_moves(ptn).unwrap();
+        for m in game.possible_moves() {
+            assert_eq!(move_from_index(move_index(&m, N), N), Some(m));
+        }
+    }
+    check::<5>(&["a1", "e5", "Cc3", "c4", "c3+", "d4"]);
+    check::<6>(&["a1", "f6", "Cc3", "c4", "c3+", "d4", "2c4>11"]);
+}
+
+#[test]
+fn transpositions_share_evaluations() {
+    let a = Game::<5>::from_ptn_moves(&["a1", "e5", "b2", "d4", "c3"]).unwrap();
+    let b = Game::<5>::from_ptn_moves(&["a1", "e5", "b2", "d4", "c2"]).unwrap();
+    let c = Game::<5>::from_ptn_moves(&["a1", "e5", "c3", "d4", "b2"]).unwrap();
+    assert_eq!(position_key(&a), position_key(&c));
+    assert_ne!(position_key(&a), position_key(&b));
+
+    // Transpositions within a batch are only evaluated once.
+    let network = CachedNetwork::new(DummyNet {}, 100);
+    let outputs = network.policy_eval(&[a, b, c.clone()]);
+    assert_eq!(outputs.len(), 3);
+    assert_eq!(network.len(), 2);
+    network.policy_eval(&[c]);
+    assert_eq!(network.hit_rate(), 0.5);
+}
+
+#[test]
+fn cached_network_evicts_least_recent() {
+    let a = Game::<6>::from_ptn_moves(&["a1", "f6", "c3"]).unwrap();
+    let b = Game::<6>::from_ptn_moves(&["a1", "f6", "d3"]).unwrap();
+    let c = Game::<6>::from_ptn_moves(&["a1", "f6", "d4"]).unwrap();
+
+    let network = CachedNetwork::new(DummyNet {}, 2);
+    network.policy_eval(&[a.clone(), b.clone()]);
+    network.policy_eval(&[a.clone()]);
+    assert_eq!(network.stats(), (1, 2));
+
+    // Inserting `c` evicts `b` since `a` was used more recently.
+    network.policy_eval(&[c, a]);
+    assert_eq!(network.stats(), (2, 3));
+    network.policy_eval(&[b]);
+    assert_eq!(network.stats(), (2, 4));
+}
+
+#[test]
+fn search_config_round_trip() {
+    let config: SearchConfig = "# comment\nfpu = -0.5\nbatch_size=16\n".parse().unwrap();
+    assert_eq!(config.fpu, Fpu::Absolute(-0.5));
+    assert_eq!(config.batch_size, 16);
+    assert_eq!(config.rollouts, SearchConfig::default().rollouts);
+    assert_eq!(config.to_string().parse::<SearchConfig>().unwrap(), config);
+
+    let overrides = vec!["rollouts=800".to_string()];
+    assert_eq!(SearchConfig::from_args(None, &overrides).unwrap().rollouts, 800);
+    assert!("unknown = 1".parse::<SearchConfig>().is_err());
+}
+
+#[test]
+fn fpu_reduction_uses_parent_reward() {
+    let game = Game::<5>::default();
+    let tree = Tree::default();
+    let node = tree.root();
+    node.rollout(game.clone(), &DummyNet {}, &SearchConfig::default());
+    assert_eq!(node.first_play_urgency(Fpu::Absolute(0.3)), 0.3);
+
+    // No children are visited yet, so there is no reduction.
+    node.update_concrete(Eval::LOSS);
+    assert_eq!(node.expected_reward(), -0.5);
+    assert_eq!(node.first_play_urgency(Fpu::Reduction(0.2)), 0.5);
+
+    node.rollout(game, &DummyNet {}, &SearchConfig::default());
+    assert!(node.first_play_urgency(Fpu::Reduction(0.2)) < 0.5);
+    assert_eq!("reduction:0.2".parse::<Fpu>().unwrap(), Fpu::Reduction(0.2));
+}
+
+#[test]
+fn concurrent_descents_share_the_tree() {
+    fn no_virtual_visits(node: NodeRef) -> bool {
+        node.virtual_visits() == 0
+            && node
+                .children()
+                .filter_map(|(_, edge)| edge.node())
+                .all(no_virtual_visits)
+    }
+
+    let game = Game::<5>::from_ptn_moves(&["a1", "e5"]).unwrap();
+    let config = SearchConfig::default();
+    let tree = Tree::default();
+    let node = tree.root();
+    node.rollout(game.clone(), &DummyNet {}, &config);
+
+    // Descend the same tree from several threads at once.
+    let barrier = Barrier::new(4);
+    let paths: Vec<_> = thread::scope(|s| {
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                s.spawn(|| {
+                    barrier.wait();
+                    (0..50)
+                        .filter_map(|_| {
+                            let mut path = vec![];
+                            let mut game = game.clone();
+                            if node.virtual_rollout(&mut game, &mut path, &config) == GameResult::Ongoing {
+                                Some((path, game))
+                            } else {
+                                None
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    assert_eq!(node.virtual_visits() as usize, paths.len());
+    // Virtual losses spread the descents over the children.
+    assert!(
+        node.children()
+            .filter(|(_, child)| child.visit_count() > 0.0)
+            .count()
+            > 1
+    );
+
+    for (path, game) in paths {
+        let net_output = &DummyNet {}.policy_eval(&[game])[0];
+        node.devirtualize_path::<5, _>(&mut path.into_iter(), net_output);
+    }
+    assert!(no_virtual_visits(node));
+    assert_eq!(node.visits(), 201);
+}
+
+#[test]
+fn threaded_player_counts_every_rollout() {
+    let network = DummyNet {};
+    let mut game = Game::<5>::from_ptn_moves(&["a1", "e5"]).unwrap();
+    let config = SearchConfig {
+        threads: 4,
+        batch_size: 8,
+        ..Default::default()
+    };
+    let mut player = Player::new(&network, config, false, false, &game);
+    for _ in 0..20 {
+        player.rollout(&game);
+    }
+    // Every evaluated batch is counted, and batches in flight add terminal visits.
+    let visits = |player: &Player<5, DummyNet>| player.debug(0).0.iter().map(|info| info.visits).sum::<u32>();
+    assert!(visits(&player) >= 20 * 8 - 1);
+
+    let my_move = player.pick_move(true);
+    player.play_move(my_move, &game, false);
+    game.play(my_move).unwrap();
+    for _ in 0..5 {
+        player.rollout(&game);
+    }
+    assert!(visits(&player) > 0);
+}
+
+#[test]
+fn inference_server_batches_requests() {
+    let (mut server, client) = inference_channel::<5>(64, Duration::from_millis(50));
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let client = client.clone();
+            spawn(move || {
+                let games = vec![Game::<5>::default(); 3];
+                client.evaluate_batch(&games).len()
+            })
+        })
+        .collect();
+    drop(client);
+    server.serve(&DummyNet {});
+
+    for worker in workers {
+        assert_eq!(worker.join().unwrap(), 3);
+    }
+    assert!(server.average_batch_size() >= 3.0);
+}
+
+#[test]
+fn decided_when_best_move_cannot_be_overtaken() {
+    let tree = Tree::default();
+    expand_root(&tree, &[
+        ("a1", 8, Proof::Unknown),
+        ("a2", 1, Proof::Unknown),
+        ("a3", 0, Proof::Unknown),
+    ]);
+    assert!(tree.root().is_decided(6));
+    assert!(!tree.root().is_decided(7));
+    assert!(!Tree::default().root().is_decided(0));
+}
+
+#[test]
+fn solver_finds_road_win() {
+    // White can complete a road on the first row.
+    let tps: takparse::Tps = "1,1,1,1,x/2,2,2,2,x/x5/x5/x5 1 5".parse().unwrap();
+    let game: Game<5> = tps.into();
+    let tree = Tree::default();
+    let node = tree.root();
+    for _ in 0..500 {
+        node.rollout(game.clone(), &DummyNet {}, &SearchConfig::default());
+    }
+    assert_eq!(node.proof(), Proof::Loss(1));
+    // Both a flat and the capstone complete the road.
+    let mut after = game.clone();
+    after.play(node.pick_move(1.0)).unwrap();
+    assert!(matches!(after.result(), GameResult::Winner {
+        color: Color::White,
+        ..
+    }));
+    let info = node.debug(1);
+    assert!(info.0.iter().any(|move_info| move_info.proof == Proof::Win(0)));
+}
+
+#[test]
+fn proven_nodes_are_not_expanded() {
+    let game = Game::<5>::from_ptn_moves(&["a1", "e5"]).unwrap();
+    let tree = Tree::default();
+    let node = tree.root();
+    node.set_proof(Proof::Win(2));
+    let mut path = Vec::new();
+    let result = node.virtual_rollout(&mut game.clone(), &mut path, &SearchConfig::default());
+    assert_eq!(result, Proof::Win(2).to_result(game.to_move));
+    assert!(!node.is_initialized());
+    assert!(path.is_empty());
+}
+
+#[test]
+fn proven_losing_move_is_never_played() {
+    let tree = Tree::default();
+    expand_root(&tree, &[
+        ("a1", 90, Proof::Loss(1)),
+        ("a2", 10, Proof::Unknown),
+        ("a3", 0, Proof::Unknown),
+    ]);
+    let node = tree.root();
+    let losing = "a1".parse().unwrap();
+    assert_ne!(node.pick_move(0.0), losing);
+    for _ in 0..100 {
+        assert_ne!(node.pick_move(1.0), losing);
+    }
+
+    // The search does not visit it again either.
+    let game = Game::<5>::default();
+    for _ in 0..50 {
+        node.rollout(game.clone(), &DummyNet {}, &SearchConfig::default());
+    }
+    assert_eq!(node.child(0).visits(), 90);
+}
+
+#[test]
+fn tactics_find_forced_road() {
+    // Filling d3 threatens to finish a road at several places at once.
+    let tps: takparse::Tps = "2,x3,2/x3,1,x/1,1,1,x2/x3,1,x/2,x,2,x,2 1 6".parse().unwrap();
+    let game: Game<5> = tps.into();
+    assert_eq!(forced_win(&game, 2), None);
+    let (my_move, plies) = forced_win(&game, 3).unwrap();
+    assert_eq!(plies, 3);
+    let mut after = game.clone();
+    after.play(my_move).unwrap();
+    for reply in after.possible_moves() {
+        let mut defended = after.clone();
+        defended.play(reply).unwrap();
+        assert!(defended.result() != GameResult::Ongoing || forced_win(&defended, 1).is_some());
+    }
+
+    let tree = Tree::default();
+    tree.root().rollout(game, &DummyNet {}, &SearchConfig::default());
+    assert_eq!(tree.root().proof(), Proof::Loss(3));
+}
+
+#[test]
+fn player_reuses_tree_after_move() {
+    let network = DummyNet {};
+    let mut game = Game::<5>::default();
+    let config = SearchConfig {
+        threads: 2,
+        batch_size: 8,
+        ..Default::default()
+    };
+    let mut player = Player::new(&network, config, false, false, &game);
+    for _ in 0..20 {
+        player.rollout(&game);
+    }
+    let my_move = player.pick_move(true);
+    let info = player.debug(1);
+    let visits = info.0.iter().find(|info| info.mov == my_move).unwrap().visits;
+    player.play_move(my_move, &game, false);
+    game.play(my_move).unwrap();
+
+    // All visits below the played move are kept, except the one which expanded it.
+    let child_visits =
+        |player: &Player<5, DummyNet>| player.debug(1).0.iter().map(|info| info.visits).sum::<u32>();
+    assert!(child_visits(&player) + 1 >= visits);
+
+    // Batches which were in flight during the move are added to the new root.
+    let before = child_visits(&player);
+    for _ in 0..4 {
+        player.rollout(&game);
+    }
+    assert!(child_visits(&player) > before);
+}
+
+#[test]
+fn played_tree_recycles_nodes() {
+    let mut game = Game::<5>::default();
+    let mut tree = Tree::default();
+    let config = SearchConfig::default();
+    let mut most = 0;
+    for _ in 0..6 {
+        for _ in 0..100 {
+            tree.root().rollout(game.clone(), &DummyNet {}, &config);
+        }
+        let used = tree.arena().len();
+        most = most.max(used);
+        let my_move = tree.root().pick_move(0.0);
+        tree.play(my_move);
+        game.play(my_move).unwrap();
+        // Only the subtree of the played move is still in use.
+        assert!(tree.arena().len() < used);
+    }
+    // Freed nodes are handed out again instead of growing the arena.
+    assert!(tree.arena().allocated() <= 2 * most);
+}
+
+#[test]
+fn garbage_collection_respects_memory_limit() {
+    let game = Game::<5>::default();
+    let config = SearchConfig::default();
+    let mut tree = Tree::default();
+    for _ in 0..300 {
+        tree.root().rollout(game.clone(), &DummyNet {}, &config);
+    }
+    // Unvisited moves are not allocated.
+    assert!(tree.arena().len() < 300 + tree.root().children().len());
+
+    let memory = tree.memory();
+    tree.collect_garbage(memory / 4);
+    assert!(tree.memory() <= memory / 4);
+    assert_eq!(tree.root().visits(), 300);
+
+    // Collapsed nodes are expanded again when they are visited.
+    for _ in 0..100 {
+        tree.root().rollout(game.clone(), &DummyNet {}, &config);
+    }
+    assert_eq!(tree.root().visits(), 400);
+}
+
+#[test]
+fn gumbel_root_only_searches_sampled_moves() {
+    let game = Game::<5>::from_ptn_moves(&["a1", "e5"]).unwrap();
+    let config = SearchConfig {
+        root_selection: RootSelection::Gumbel(4),
+        ..Default::default()
+    };
+    let tree = Tree::default();
+    let node = tree.root();
+    node.rollout(game.clone(), &DummyNet {}, &config);
+    let mut gumbel = node.gumbel_root(&config, 32).unwrap();
+    for _ in 0..32 {
+        let mut path = Vec::new();
+        let mut game = game.clone();
+        let action = gumbel.next_action(node).unwrap();
+        if node.gumbel_rollout(action, &mut game, &mut path, &config) == GameResult::Ongoing {
+            let output = DummyNet {}.evaluate_batch(&[game]).remove(0);
+            node.devirtualize_path::<5, _>(&mut path.into_iter(), &output);
+        }
+    }
+    assert_eq!(node.children().filter(|(_, edge)| edge.visits() > 0).count(), 4);
+
+    // The completed policy covers unvisited moves as well.
+    let my_move = gumbel.pick_move(node);
+    let policy = node.completed_policy();
+    assert!(policy.iter().any(|(mov, _)| *mov == my_move));
+    assert!(policy.iter().all(|(_, weight)| *weight > 0));
+    assert_eq!(
+        "gumbel:16".parse::<RootSelection>().unwrap(),
+        RootSelection::Gumbel(16)
+    );
+}
+
+#[test]
+fn gumbel_never_plays_proven_loss() {
+    // White threatens to finish a road at c1, so every other move loses.
+    let game = Game::<3>::from_ptn_moves(&["a3", "c3", "c2"]).unwrap();
+    let config = SearchConfig {
+        root_selection: RootSelection::Gumbel(16),
+        tactics_depth: 1,
+        rollouts: 10,
+        batch_size: 8,
+        ..Default::default()
+    };
+    for _ in 0..10 {
+        let mut player = Player::new(&DummyNet {}, config.clone(), false, false, &game);
+        for _ in 0..config.rollouts {
+            player.rollout(&game);
+        }
+        let losses = player
+            .debug(0)
+            .0
+            .iter()
+            .filter(|info| info.proof.is_loss())
+            .count();
+        assert!(losses > 0);
+
+        let mut after = game.clone();
+        after.play(player.pick_move(false)).unwrap();
+        assert_eq!(forced_win(&after, 1), None);
+    }
+
+    // A proven win is played straight away.
+    let tree = Tree::default();
+    expand_root(&tree, &[
+        ("a1", 10, Proof::Unknown),
+        ("a2", 0, Proof::Win(0)),
+        ("b1", 5, Proof::Loss(1)),
+    ]);
+    tree.root().visits.store(16, Ordering::Relaxed);
+    let gumbel = GumbelRoot::new(tree.root(), 3, 16);
+    assert_eq!(gumbel.pick_move(tree.root()), "a2".parse().unwrap());
+}
+
+#[test]
+fn temperature_schedule_and_visit_cutoff() {
+    let config = SearchConfig {
+        temperature: 1.0,
+        final_temperature: 0.2,
+        temperature_half_life: 10,
+        ..Default::default()
+    };
+    assert_eq!(config.temperature_at(0), 1.0);
+    assert!((config.temperature_at(10) - 0.6).abs() < 1e-6);
+    assert!(config.temperature_at(100) < 0.21);
+
+    let tree = Tree::default();
+    expand_root(&tree, &[
+        ("a1", 60, Proof::Unknown),
+        ("a2", 40, Proof::Unknown),
+        ("a3", 1, Proof::Unknown),
+    ]);
+    for _ in 0..100 {
+        assert_ne!(tree.root().pick_move_pruned(10.0, 0.5), "a3".parse().unwrap());
+    }
+}
+
+#[test]
+fn search_report_describes_tree() {
+    let game = Game::<5>::from_ptn_moves(&["a1", "e5"]).unwrap();
+    let tree = Tree::default();
+    for _ in 0..200 {
+        tree.root()
+            .rollout(game.clone(), &DummyNet {}, &SearchConfig::default());
+    }
+    let report = SearchReport::from_node(tree.root(), 5);
+    assert_eq!(report.nodes, 200);
+    assert_eq!(report.depth_distribution.iter().sum::<u32>(), 200);
+    assert_eq!(report.depth_distribution[0], 1);
+    assert!(report.selective_depth() >= 2);
+    assert!(!report.pv.is_empty() && report.pv.len() <= 5);
+    assert_eq!(report.pv[0].mov, tree.root().pick_move(0.0));
+    assert_eq!(report.eval, -tree.root().expected_reward());
+
+    let json = report.with_cache_hit_rate(0.5).to_json();
+    assert!(json.starts_with("{\"nodes\":200,"));
+    assert!(json.contains("\"win_probability\":"));
+    assert!(json.contains("\"cache_hit_rate\":0.5000"));
+
+    // JSON has no representation for NaN or infinity.
+    let json = SearchReport::default().with_cache_hit_rate(f64::NAN).to_json();
+    assert!(json.contains("\"cache_hit_rate\":null"));
+    assert!(!json.contains("NaN") && !json.contains("inf"));
+}
+
+#[test]
+fn multi_pv_guarantees_visits_to_top_moves() {
+    let game = Game::<5>::from_ptn_moves(&["a1", "e5"]).unwrap();
+    let config = SearchConfig {
+        multi_pv: 3,
+        multi_pv_share: 0.6,
+        ..Default::default()
+    };
+    let tree = Tree::default();
+    for _ in 0..300 {
+        tree.root().rollout(game.clone(), &DummyNet {}, &config);
+    }
+    let report = SearchReport::from_node(tree.root(), 5).with_lines(tree.root(), 3, 5);
+    assert_eq!(report.lines.len(), 3);
+    for line in &report.lines {
+        // Each line gets its share of the visits, minus one still being decided.
+        assert!(line[0].visits as f32 >= 300.0 * 0.6 / 3.0 - 1.0);
+    }
+    assert!(report.to_json().contains("\"lines\":[["));
+}
+
+#[test]
+fn move_filter_restricts_root() {
+    let game = Game::<5>::from_ptn_moves(&["a1", "e5"]).unwrap();
+    let config = SearchConfig::default();
+    let mut tree = Tree::default();
+    tree.root().rollout(game.clone(), &DummyNet {}, &config);
+
+    let allowed: Vec<Move> = ["b2", "c3", "Sd4"].iter().map(|m| m.parse().unwrap()).collect();
+    assert!(!tree.retain_moves(&MoveFilter::Only(vec!["a1".parse().unwrap()])));
+    assert!(tree.retain_moves(&MoveFilter::Only(allowed.clone())));
+    assert_eq!(tree.root().children().len(), 3);
+
+    for _ in 0..50 {
+        tree.root().rollout(game.clone(), &DummyNet {}, &config);
+    }
+    assert!(allowed.contains(&tree.root().pick_move(1.0)));
+    // The subtree of the excluded move is freed.
+    let nodes = tree.arena().len();
+    assert!(tree.retain_moves(&MoveFilter::Exclude(vec!["c3".parse().unwrap()])));
+    assert_eq!(tree.root().children().len(), 2);
+    assert!(tree.arena().len() < nodes);
+}
+
+#[test]
+fn noise_with_single_allowed_move() {
+    let network = DummyNet {};
+    let game = Game::<5>::from_ptn_moves(&["a1", "e5"]).unwrap();
+    let mut player = Player::new(&network, SearchConfig::default(), false, false, &game);
+    player.rollout(&game);
+    let only: Move = "c3".parse().unwrap();
+    assert!(player.restrict_moves(&game, &MoveFilter::Only(vec![only])));
+    player.add_noise(&game);
+    for _ in 0..4 {
+        player.rollout(&game);
+    }
+    assert_eq!(player.pick_move(false), only);
+}
+
+#[test]
+fn evaluate_without_player() {
+    let tps: takparse::Tps = "1,1,1,1,x/2,2,2,2,x/x5/x5/x5 1 5".parse().unwrap();
+    let game: Game<5> = tps.into();
+    let result = evaluate(&game, &DummyNet {}, &SearchConfig::default(), 1000);
+    assert_eq!(result.best_move, Some("e5".parse().unwrap()));
+    assert_eq!(result.proof, Proof::Win(1));
+    assert!(result.visits < 1000);
+
+    let result = evaluate(&Game::<5>::default(), &DummyNet {}, &SearchConfig::default(), 100);
+    assert_eq!(result.visits, 100);
+    assert_eq!(result.pv.first().map(|entry| entry.mov), result.best_move);
+}
+
+/// Predicts that every position is a draw with some probability.
+struct DrawishNet {}
+
+impl<const N: usize> Evaluator<N> for DrawishNet {
+    fn evaluate_batch(&self, games: &[Game<N>]) -> Vec<(Policy, Eval)> {
+        vec![(vec![1.0; output_size(N)], Eval::from_wdl(0.3, 0.6, 0.1)); games.len()]
+    }
+}
+
+#[test]
+fn wdl_evaluations_back_up_draw_probability() {
+    let (win, draw, loss) = Eval::from_wdl(0.3, 0.6, 0.1).flip().wdl();
+    assert!((win - 0.1).abs() < 1e-6 && (draw - 0.6).abs() < 1e-6 && (loss - 0.3).abs() < 1e-6);
+
+    let game = Game::<5>::from_ptn_moves(&["a1", "e5"]).unwrap();
+    let tree = Tree::default();
+    for _ in 0..100 {
+        tree.root()
+            .rollout(game.clone(), &DrawishNet {}, &SearchConfig::default());
+    }
+    assert!((tree.root().draw_probability() - 0.6).abs() < 1e-4);
+    let info = tree.root().debug(1);
+    assert!(info
+        .0
+        .iter()
+        .filter(|info| info.visits > 0)
+        .all(|info| (info.draw - 0.6).abs() < 1e-4));
+    assert!(info.0[0].ptn_comment(false).contains("d: 0.600"));
+}
@@ -0,0 +1,82 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use tak::*;
+
+use super::move_map::move_index;
+use crate::model::network::{Eval, Policy};
+
+// Seeds of the two hashes in a position key.
+const HASH_SEED: u64 = 0;
+const CHECK_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Identifies a position for sharing network evaluations.
+/// The hash selects the entry and the check, a second hash with a different
+/// seed, is compared on lookup so that a collision of the hash alone
+/// does not return the output for another position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PositionKey {
+    pub hash: u64,
+    pub check: u64,
+}
+
+/// Hash everything the network sees about a position.
+/// Positions which only differ in move order map to the same key.
+pub fn position_key<const N: usize>(game: &Game<N>) -> PositionKey {
+    PositionKey {
+        hash: hash_position(game, HASH_SEED),
+        check: hash_position(game, CHECK_SEED),
+    }
+}
+
+fn hash_position<const N: usize>(game: &Game<N>, seed: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    game.board.hash(&mut hasher);
+    game.to_move.hash(&mut hasher);
+    (
+        game.white_stones,
+        game.white_caps,
+        game.black_stones,
+        game.black_caps,
+        game.half_komi,
+        game.ply < 2,
+    )
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Network output with the policy reduced to the legal moves.
+#[derive(Clone, Debug)]
+pub(crate) struct CompactOutput {
+    policy_len: usize,
+    policy: Box<[(usize, f32)]>,
+    eval: Eval,
+}
+
+impl CompactOutput {
+    pub(crate) fn new<const N: usize>(game: &Game<N>, (policy, eval): &(Policy, Eval)) -> Self {
+        CompactOutput {
+            policy_len: policy.len(),
+            policy: game
+                .possible_moves()
+                .iter()
+                .map(|m| {
+                    let index = move_index(m, N);
+                    (index, policy[index])
+                })
+                .collect(),
+            eval: *eval,
+        }
+    }
+
+    pub(crate) fn output(&self) -> (Policy, Eval) {
+        let mut policy = vec![0.0; self.policy_len];
+        for &(index, p) in self.policy.iter() {
+            policy[index] = p;
+        }
+        (policy, self.eval)
+    }
+}
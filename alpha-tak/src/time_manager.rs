@@ -0,0 +1,175 @@
+use std::time::{Duration, Instant};
+
+use tak::*;
+
+use crate::search::NodeDebugInfo;
+
+// Time reserved for network latency and move transmission.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(300);
+// Games rarely end before this many of our own moves remain.
+const MIN_MOVES_LEFT: u32 = 10;
+// Never spend more than this fraction of the remaining time on one move.
+const MAX_TIME_FRACTION: u32 = 5;
+// How far past the soft limit an unstable search may go.
+const HARD_LIMIT_FACTOR: u32 = 4;
+// How much each change of best move extends the soft limit.
+const INSTABILITY_BONUS: f32 = 0.3;
+const MAX_INSTABILITY_BONUS: f32 = 1.5;
+
+/// Decides how long to think about a move.
+///
+/// The search is stopped once the soft limit is reached,
+/// unless the best move keeps changing, in which case it may
+/// continue up to the hard limit. It also stops early when the
+/// best move can no longer be overtaken before the hard limit.
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Duration,
+    hard_limit: Duration,
+    best_move: Option<Move>,
+    best_move_changes: u32,
+    start_visits: Option<u32>,
+}
+
+impl TimeManager {
+    /// Budget time for a move given the clock state.
+    pub fn new(remaining: Duration, increment: Duration, ply: u16, board_size: usize) -> Self {
+        let usable = remaining.saturating_sub(MOVE_OVERHEAD);
+        // Expect longer games on bigger boards.
+        let expected_plies = (board_size * board_size * 3) as u32;
+        let moves_left = (expected_plies.saturating_sub(ply as u32) / 2).max(MIN_MOVES_LEFT);
+
+        let soft_limit = usable / moves_left + increment * 3 / 4;
+        let hard_limit = (soft_limit * HARD_LIMIT_FACTOR).min(usable / MAX_TIME_FRACTION + increment);
+        Self::with_limits(soft_limit.min(hard_limit), hard_limit)
+    }
+
+    /// Think for a fixed amount of time, but allow stopping early
+    /// when the best move is decided.
+    pub fn fixed(time: Duration) -> Self {
+        Self::with_limits(time, time)
+    }
+
+    fn with_limits(soft_limit: Duration, hard_limit: Duration) -> Self {
+        TimeManager {
+            start: Instant::now(),
+            soft_limit,
+            hard_limit,
+            best_move: None,
+            best_move_changes: 0,
+            start_visits: None,
+        }
+    }
+
+    pub fn soft_limit(&self) -> Duration {
+        self.soft_limit
+    }
+
+    pub fn hard_limit(&self) -> Duration {
+        self.hard_limit
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Check whether the search should stop, given the current state of the
+    /// root. This should be called regularly during the search.
+    pub fn should_stop(&mut self, info: &NodeDebugInfo) -> bool {
+        self.should_stop_at(info, self.elapsed())
+    }
+
+    /// Like [`TimeManager::should_stop`], but with the elapsed time given
+    /// instead of read from the clock.
+    pub fn should_stop_at(&mut self, info: &NodeDebugInfo, elapsed: Duration) -> bool {
+        // Visits reused from an earlier search do not tell us how fast we search.
+        let visits: u32 = info.0.iter().map(|move_info| move_info.visits).sum();
+        let start_visits = *self.start_visits.get_or_insert(visits);
+
+        if elapsed >= self.hard_limit {
+            return true;
+        }
+
+        // Moves are sorted by visits.
+        let (best, second) = match info.0.as_slice() {
+            [] => return true,
+            [only] => (only, None),
+            [best, second, ..] => (best, Some(second)),
+        };
+        if self.best_move.is_some_and(|m| m != best.mov) {
+            self.best_move_changes += 1;
+        }
+        self.best_move = Some(best.mov);
+
+        // Give unstable searches more time.
+        let bonus = (INSTABILITY_BONUS * self.best_move_changes as f32).min(MAX_INSTABILITY_BONUS);
+        if elapsed >= self.soft_limit.mul_f32(1.0 + bonus).min(self.hard_limit) {
+            return true;
+        }
+
+        // Stop if the second best move cannot catch up before the hard limit.
+        let second = match second {
+            Some(second) => second,
+            None => return true,
+        };
+        let searched = visits.saturating_sub(start_visits);
+        if searched == 0 || elapsed.is_zero() {
+            return false;
+        }
+        let rate = searched as f64 / elapsed.as_secs_f64();
+        let visits_left = rate * (self.hard_limit - elapsed).as_secs_f64();
+        visits_left < (best.visits - second.visits) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{MoveInfo, NodeDebugInfo, TimeManager, DEFAULT_LOGIT_PER_FLAT};
+
+    fn info(visits: &[u32]) -> NodeDebugInfo {
+        NodeDebugInfo(
+            visits
+                .iter()
+                .zip(["a1", "b1", "c1"])
+                .map(|(&visits, mov)| MoveInfo {
+                    mov: mov.parse().unwrap(),
+                    visits,
+                    reward: 0.0,
+                    draw: 0.0,
+                    policy: 0.0,
+                    proof: Default::default(),
+                    continuation: Default::default(),
+                })
+                .collect(),
+            DEFAULT_LOGIT_PER_FLAT,
+        )
+    }
+
+    #[test]
+    fn budget_fits_clock() {
+        let time = TimeManager::new(Duration::from_secs(60), Duration::from_secs(2), 10, 6);
+        assert!(time.soft_limit() <= time.hard_limit());
+        assert!(time.hard_limit() < Duration::from_secs(20));
+        assert!(time.soft_limit() > Duration::from_secs(1));
+    }
+
+    #[test]
+    fn stops_when_decided() {
+        let mut time = TimeManager::fixed(Duration::from_millis(100));
+        assert!(!time.should_stop_at(&info(&[10, 9, 1]), Duration::ZERO));
+        // At the current rate the second move cannot catch up in the remaining time.
+        assert!(time.should_stop_at(&info(&[1000, 0, 0]), Duration::from_millis(60)));
+        assert!(TimeManager::fixed(Duration::ZERO).should_stop(&info(&[10, 9, 1])));
+    }
+
+    #[test]
+    fn rate_ignores_reused_visits() {
+        let mut time = TimeManager::fixed(Duration::from_millis(100));
+        assert!(!time.should_stop_at(&info(&[5000, 4000, 0]), Duration::ZERO));
+        // Only 100 visits were searched, so the gap of 1100 cannot be closed.
+        assert!(time.should_stop_at(&info(&[5100, 4000, 0]), Duration::from_millis(50)));
+    }
+}
@@ -7,9 +7,15 @@ pub struct Args {
     pub board_size: usize,
     /// Path to model
     pub model_path: String,
-    /// How many virtual rollouts to perform per batch
-    #[clap(short, long, default_value_t = 64)]
-    pub batch_size: u32,
+    /// How many virtual rollouts to perform per batch (overrides the config)
+    #[clap(short, long)]
+    pub batch_size: Option<u32>,
+    /// Path to a search config file
+    #[clap(short, long)]
+    pub config: Option<String>,
+    /// Override a search parameter, for example `--set fpu=-0.2`
+    #[clap(long = "set")]
+    pub overrides: Vec<String>,
     /// Path to PTN game file
     #[clap(short, long)]
     pub ptn_file: Option<String>,
@@ -22,6 +28,9 @@ pub struct Args {
     /// Run an example game
     #[clap(short, long)]
     pub example_game: bool,
+    /// Number of network evaluations to cache (0 disables the cache)
+    #[clap(long, default_value_t = 100_000)]
+    pub cache_size: usize,
     /// Number of seconds to think
     #[clap(short, long, default_value_t = 15)]
     pub think_seconds: u64,
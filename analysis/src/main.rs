@@ -7,10 +7,24 @@ use std::{
     io::{stdout, Write},
     sync::mpsc::channel,
     thread,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
-use alpha_tak::{use_cuda, Net5, Net6, Network, Player};
+use alpha_tak::{
+    inference_channel,
+    use_cuda,
+    CacheStats,
+    CachedNetwork,
+    Evaluator,
+    InferenceClient,
+    MoveFilter,
+    Net5,
+    Net6,
+    Network,
+    Player,
+    SearchConfig,
+    TimeManager,
+};
 use clap::Parser;
 use cli::Args;
 use mimalloc::MiMalloc;
@@ -23,12 +37,19 @@ static GLOBAL: MiMalloc = MiMalloc;
 mod cli;
 mod parse;
 
+// How long the inference server waits for more positions.
+const MAX_LATENCY: Duration = Duration::from_millis(1);
+
 const HELP_MESSAGE: &str = "\
 help    - shows this message
 finish  - ends the game and creates an analysis file
 undo    - return to the previous position (resets nodes and analysis)
 tps     - shows the current board as TPS
 nps     - shows the nodes per second (since last move)
+cache   - shows the network evaluation cache hit rate
+report  - shows the search statistics as JSON
+only    - only searches the given moves, e.g. `only a1 b2`
+exclude - does not search the given moves, e.g. `exclude Sc3`
 [empty] - shows the network evaluation
 [move]  - plays the move
 ";
@@ -47,27 +68,40 @@ fn main() {
     }
 }
 
+/// Run the analysis on another thread, while this thread evaluates positions
+/// for it through an inference server.
 fn generic_main<const N: usize, NET: Network<N>>(args: Args) {
-    if args.ptn_file.is_some() {
-        analyze_file::<N, NET>(args);
-    } else if args.example_game {
-        run_example_game::<N, NET>(args);
-    } else {
-        interactive_analysis::<N, NET>(args);
-    }
+    let network = get_model::<N, NET>(&args);
+    let stats = network.stats_handle();
+    // The player sends one batch at a time.
+    let (mut server, client) = inference_channel(get_config(&args).batch_size as usize, MAX_LATENCY);
+
+    let worker = thread::spawn(move || {
+        if args.ptn_file.is_some() {
+            analyze_file(&client, args);
+        } else if args.example_game {
+            run_example_game(&client, args);
+        } else {
+            interactive_analysis(&client, stats, args);
+        }
+    });
+    // The server stops once the worker has dropped its client.
+    server.serve(&network);
+    worker.join().unwrap();
 }
 
 /// Take a file and generate an analysis.
-fn analyze_file<const N: usize, NET: Network<N>>(args: Args) {
-    let network: NET = get_model(&args);
-    let file = read_to_string(args.ptn_file.unwrap()).unwrap();
+fn analyze_file<const N: usize>(network: &InferenceClient<N>, args: Args) {
+    let file = read_to_string(args.ptn_file.as_ref().unwrap()).unwrap();
     let (mut game, moves): (Game<N>, _) = parse_ptn(&file).unwrap();
-    let mut player = Player::new(&network, args.batch_size, false, true, &game);
+    let mut player = new_player(network, &args, &game);
     let think_time = Duration::from_secs(args.think_seconds);
 
     for my_move in moves {
-        let start = Instant::now();
-        while Instant::now().duration_since(start) < think_time {
+        // Stop early if the best move is already decided.
+        let mut time = TimeManager::fixed(think_time);
+        player.rollout(&game);
+        while !time.should_stop(&player.debug(1)) {
             player.rollout(&game);
         }
         println!(
@@ -83,10 +117,9 @@ fn analyze_file<const N: usize, NET: Network<N>>(args: Args) {
 }
 
 /// Run a game with the bot playing against itself
-fn run_example_game<const N: usize, NET: Network<N>>(args: Args) {
-    let network: NET = get_model(&args);
+fn run_example_game<const N: usize>(network: &InferenceClient<N>, args: Args) {
     let mut game = Game::<N>::with_komi(2);
-    let mut player = Player::new(&network, args.batch_size, false, true, &game);
+    let mut player = new_player(network, &args, &game);
 
     // TODO allow custom openings
     // (and also make them work for different board sizes)
@@ -99,8 +132,10 @@ fn run_example_game<const N: usize, NET: Network<N>>(args: Args) {
     let think_time = Duration::from_secs(args.think_seconds);
 
     while game.result() == GameResult::Ongoing {
-        let start = Instant::now();
-        while Instant::now().duration_since(start) < think_time {
+        // Stop early if the best move is already decided.
+        let mut time = TimeManager::fixed(think_time);
+        player.rollout(&game);
+        while !time.should_stop(&player.debug(1)) {
             player.rollout(&game);
         }
         let my_move = player.pick_move(true);
@@ -117,14 +152,13 @@ fn run_example_game<const N: usize, NET: Network<N>>(args: Args) {
 
 /// Run an interactive analysis where the user can input moves and see
 /// intermediate evaluations.
-fn interactive_analysis<const N: usize, NET: Network<N>>(args: Args) {
-    let network: NET = get_model(&args);
+fn interactive_analysis<const N: usize>(network: &InferenceClient<N>, stats: CacheStats, args: Args) {
     let mut game = if let Some(s) = args.from_position.clone() {
         parse_position(&s).unwrap()
     } else {
         Game::<N>::with_komi(2)
     };
-    let mut player = Player::new(&network, args.batch_size, false, true, &game);
+    let mut player = new_player(network, &args, &game);
 
     let mut past_game_states = vec![game.clone()];
 
@@ -135,13 +169,9 @@ fn interactive_analysis<const N: usize, NET: Network<N>>(args: Args) {
             tx.send(get_input()).unwrap();
         });
 
-        let start = Instant::now();
-        let mut nodes: u64 = 0;
-
         loop {
             // Do rollouts while we wait for input.
             player.rollout(&game);
-            nodes += args.batch_size as u64;
 
             if let Ok(input) = rx.try_recv() {
                 clear_screen();
@@ -151,6 +181,10 @@ fn interactive_analysis<const N: usize, NET: Network<N>>(args: Args) {
                         "{:.10}",
                         player.debug(10).maybe_flip(game.to_move == Color::Black)
                     );
+                    if let Some(flats) = network.flat_difference(std::slice::from_ref(&game)) {
+                        let flip = if game.to_move == Color::Black { -1.0 } else { 1.0 };
+                        println!("predicted flat difference: {:+.2}", flip * flats[0]);
+                    }
                 } else if trim == "help" {
                     println!("{HELP_MESSAGE}");
                 } else if trim == "finish" {
@@ -158,7 +192,7 @@ fn interactive_analysis<const N: usize, NET: Network<N>>(args: Args) {
                 } else if trim == "undo" {
                     if let Some(prev) = past_game_states.pop() {
                         // Currently also resets the analysis file
-                        player = Player::new(&network, args.batch_size, false, true, &prev);
+                        player = new_player(network, &args, &prev);
                         game = prev;
                         println!("undo complete");
                     } else {
@@ -168,10 +202,19 @@ fn interactive_analysis<const N: usize, NET: Network<N>>(args: Args) {
                     let tps: Tps = game.clone().into();
                     println!("{tps}");
                 } else if trim == "nps" {
-                    let now = Instant::now();
-                    let delta = now.duration_since(start).as_secs_f64();
-                    let nps = nodes as f64 / delta;
-                    println!("{nps:.1} nodes per second")
+                    println!("{:.1} nodes per second", player.report().nps())
+                } else if trim == "report" {
+                    println!(
+                        "{}",
+                        player.report().with_cache_hit_rate(stats.hit_rate()).to_json()
+                    )
+                } else if let Some(moves) = trim.strip_prefix("only ") {
+                    restrict_moves(&mut player, &game, moves, MoveFilter::Only);
+                } else if let Some(moves) = trim.strip_prefix("exclude ") {
+                    restrict_moves(&mut player, &game, moves, MoveFilter::Exclude);
+                } else if trim == "cache" {
+                    let (hits, misses) = stats.get();
+                    println!("{hits} hits, {misses} misses ({:.3} hit rate)", stats.hit_rate());
                 } else {
                     let prev = game.clone();
                     match try_play_move(&mut player, &mut game, input) {
@@ -187,12 +230,30 @@ fn interactive_analysis<const N: usize, NET: Network<N>>(args: Args) {
     save_analysis(player, args.from_position)
 }
 
-fn get_model<const N: usize, NET: Network<N>>(args: &Args) -> NET {
-    if args.model_path == "random" {
+fn get_config(args: &Args) -> SearchConfig {
+    let mut config = SearchConfig::from_args(args.config.as_deref(), &args.overrides)
+        .unwrap_or_else(|e| panic!("could not load search config: {e}"));
+    if let Some(batch_size) = args.batch_size {
+        config.batch_size = batch_size;
+    }
+    config
+}
+
+fn new_player<'a, const N: usize, NET: Evaluator<N>>(
+    network: &'a NET,
+    args: &Args,
+    game: &Game<N>,
+) -> Player<'a, N, NET> {
+    Player::new(network, get_config(args), false, true, game)
+}
+
+fn get_model<const N: usize, NET: Network<N>>(args: &Args) -> CachedNetwork<NET> {
+    let network = if args.model_path == "random" {
         NET::default()
     } else {
         NET::load(&args.model_path).unwrap_or_else(|_| panic!("could not load model at {}", args.model_path))
-    }
+    };
+    CachedNetwork::new(network, args.cache_size)
 }
 
 fn clear_screen() {
@@ -208,7 +269,7 @@ fn get_input() -> String {
     line
 }
 
-fn try_play_move<const N: usize, NET: Network<N>>(
+fn try_play_move<const N: usize, NET: Evaluator<N>>(
     player: &mut Player<'_, N, NET>,
     game: &mut Game<N>,
     input: String,
@@ -219,7 +280,30 @@ fn try_play_move<const N: usize, NET: Network<N>>(
     Ok(())
 }
 
-fn save_analysis<const N: usize, NET: Network<N>>(mut player: Player<N, NET>, from_position: Option<String>) {
+fn restrict_moves<const N: usize, NET: Evaluator<N>>(
+    player: &mut Player<'_, N, NET>,
+    game: &Game<N>,
+    moves: &str,
+    filter: fn(Vec<Move>) -> MoveFilter,
+) {
+    let moves = match moves.split_whitespace().map(str::parse).collect() {
+        Ok(moves) => moves,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    if player.restrict_moves(game, &filter(moves)) {
+        println!("restricted the search")
+    } else {
+        println!("no legal move is allowed")
+    }
+}
+
+fn save_analysis<const N: usize, NET: Evaluator<N>>(
+    mut player: Player<N, NET>,
+    from_position: Option<String>,
+) {
     let mut analysis = player.get_analysis();
     if let Some(tps) = from_position {
         analysis.add_setting("TPS", tps);
@@ -0,0 +1,34 @@
+use clap::{Parser, Subcommand};
+
+/// Check rules conformance against other Tak engines
+#[derive(Parser)]
+pub struct Args {
+    /// Board size
+    pub board_size: usize,
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Play random games and dump a record for every position
+    Generate {
+        /// Number of games to play
+        #[clap(short, long, default_value_t = 100)]
+        games: u32,
+        /// Seed for the random move choice
+        #[clap(short, long, default_value_t = 0)]
+        seed: u64,
+        /// Half komi used in the games
+        #[clap(long, default_value_t = 4)]
+        half_komi: i8,
+        /// Path to output file, prints to stdout if missing
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    /// Check every record in a corpus file and report mismatches
+    Check {
+        /// Path to corpus file
+        corpus: String,
+    },
+}
@@ -0,0 +1,98 @@
+use std::{
+    fs::{read_to_string, File},
+    io::{stdout, Write},
+    process::exit,
+};
+
+use clap::Parser;
+use cli::{Args, Command};
+use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
+use tak::*;
+
+mod cli;
+
+fn main() {
+    let args = Args::parse();
+    match args.board_size {
+        3 => generic_main::<3>(args.command),
+        4 => generic_main::<4>(args.command),
+        5 => generic_main::<5>(args.command),
+        6 => generic_main::<6>(args.command),
+        7 => generic_main::<7>(args.command),
+        8 => generic_main::<8>(args.command),
+        n => println!("Unsupported board size: {n}"),
+    }
+}
+
+fn generic_main<const N: usize>(command: Command) {
+    match command {
+        Command::Generate {
+            games,
+            seed,
+            half_komi,
+            output,
+        } => {
+            let mut out: Box<dyn Write> = match output {
+                Some(path) => Box::new(File::create(path).unwrap()),
+                None => Box::new(stdout()),
+            };
+            generate::<N>(&mut out, games, seed, half_komi);
+        }
+        Command::Check { corpus } => {
+            if !check::<N>(&corpus) {
+                exit(1);
+            }
+        }
+    }
+}
+
+/// Play random games and write a record for every position reached.
+fn generate<const N: usize>(out: &mut dyn Write, games: u32, seed: u64, half_komi: i8) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..games {
+        let mut game = Game::<N>::with_half_komi(half_komi);
+        loop {
+            writeln!(out, "{}", Record::from_game(&game)).unwrap();
+            if game.result() != GameResult::Ongoing {
+                break;
+            }
+            let my_move = *game.possible_moves().choose(&mut rng).unwrap();
+            game.play(my_move).unwrap();
+        }
+    }
+    out.flush().unwrap();
+}
+
+/// Check all records in the corpus. Returns whether they all matched.
+fn check<const N: usize>(corpus: &str) -> bool {
+    let file = read_to_string(corpus).unwrap_or_else(|err| panic!("could not read {corpus}: {err}"));
+
+    let mut records = 0;
+    let mut failed = 0;
+    for (line_num, line) in file
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+    {
+        records += 1;
+        let record: Record = match line.parse() {
+            Ok(record) => record,
+            Err(err) => {
+                failed += 1;
+                println!("line {}: could not parse record: {err}", line_num + 1);
+                continue;
+            }
+        };
+        let mismatches = record.check::<N>();
+        if !mismatches.is_empty() {
+            failed += 1;
+            println!("line {}: {}", line_num + 1, record.tps);
+            for mismatch in mismatches {
+                println!("    {mismatch}");
+            }
+        }
+    }
+
+    println!("{failed}/{records} records did not match");
+    failed == 0
+}
@@ -2,40 +2,34 @@ use std::{
     fs::{write, File},
     io::Write,
     thread,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
-use alpha_tak::{sys_time, Net6, Network, Player};
+use alpha_tak::{forced_win, sys_time, CachedNetwork, Net6, Network, Player, SearchConfig, TimeManager};
 use tak::*;
 use tokio::sync::mpsc::{error::TryRecvError, UnboundedReceiver, UnboundedSender};
 use tokio_takconnect::data_types::WinReason;
 
-use crate::{
-    cli::Args,
-    message::Message,
-    ANALYSIS_DIR,
-    EXAMPLE_DIR,
-    KOMI,
-    OPENING_BOOK,
-    PONDER_ROLLOUT_LIMIT,
-    WHITE_FIRST_MOVE,
-};
+use crate::{cli::Args, message::Message, ANALYSIS_DIR, EXAMPLE_DIR, KOMI, OPENING_BOOK, WHITE_FIRST_MOVE};
 
 pub fn run_bot(args: Args, tx: UnboundedSender<Message>, mut rx: UnboundedReceiver<Message>) {
     let model_path = &args.model_path;
     let network = Net6::load(model_path).unwrap_or_else(|_| panic!("could not load model at {model_path}"));
+    let network = CachedNetwork::new(network, args.cache_size);
+    let config = SearchConfig::from_args(args.config.as_deref(), &args.overrides)
+        .unwrap_or_else(|e| panic!("could not load search config: {e}"));
 
     let mut example_file = File::create(format!("{EXAMPLE_DIR}/playtak_{}.data", sys_time())).unwrap();
 
     'game_loop: loop {
         let mut game = Game::<6>::with_komi(KOMI as i8);
-        let mut player = Player::new(&network, 64, true, true, &game);
+        let mut player = Player::new(&network, config.clone(), true, true, &game);
         let mut last_move: String = String::new();
         let mut ponder_rollouts = 0;
         let mut game_info = None;
 
         let game_result = 'turn_loop: loop {
-            match if ponder_rollouts < PONDER_ROLLOUT_LIMIT && game.ply > 0 {
+            match if ponder_rollouts < args.ponder_rollout_limit && game.ply > 0 {
                 rx.try_recv()
             } else {
                 rx.blocking_recv().ok_or(TryRecvError::Disconnected)
@@ -46,27 +40,26 @@ pub fn run_bot(args: Args, tx: UnboundedSender<Message>, mut rx: UnboundedReceiv
                 }
 
                 // Play a move.
-                Ok(Message::MoveRequest) => {
+                Ok(Message::MoveRequest { remaining, increment }) => {
+                    // Start timing right away, so that every reply is counted.
+                    let mut time = match args.time_to_think {
+                        Some(seconds) => TimeManager::fixed(Duration::from_secs(seconds)),
+                        None => TimeManager::new(remaining, increment, game.ply, 6),
+                    };
                     println!("Did {ponder_rollouts} ponder rollouts.");
                     ponder_rollouts = 0;
 
-                    println!("A move has been requested.");
+                    println!(
+                        "A move has been requested ({:.1}s left).",
+                        remaining.as_secs_f32()
+                    );
                     if game.result() != GameResult::Ongoing {
                         tx.send(Message::GameEnded(None)).unwrap();
                         continue;
                     }
 
                     // Check for moves that win on the spot.
-                    let mut instant_win = None;
-                    for my_move in game.possible_moves() {
-                        let mut clone = game.clone();
-                        clone.play(my_move).unwrap();
-                        if matches!(clone.result(), GameResult::Winner { color, .. } if color == game.to_move)
-                        {
-                            instant_win = Some(my_move);
-                            break;
-                        }
-                    }
+                    let instant_win = forced_win(&game, 1).map(|(my_move, _plies)| my_move);
 
                     let mut book = None;
                     if game.ply == 1 {
@@ -87,18 +80,22 @@ pub fn run_bot(args: Args, tx: UnboundedSender<Message>, mut rx: UnboundedReceiv
                         (book_turn, false)
                     } else {
                         println!("Doing rollouts...");
-                        // Do rollouts for a set amount of time.
-                        let start = Instant::now();
-                        while Instant::now().duration_since(start) < Duration::from_secs(args.time_to_think) {
+                        player.rollout(&game);
+                        while !time.should_stop(&player.debug(1)) {
                             player.rollout(&game);
                         }
+                        log::info!(
+                            "search report: {}",
+                            player.report().with_cache_hit_rate(network.hit_rate()).to_json()
+                        );
                         print!("{:.10}", player.debug(5).maybe_flip(game.to_move == Color::Black));
 
-                        (player.pick_move(true), true)
+                        (player.pick_move(game.ply >= args.sample_plies), true)
                     };
 
                     player.play_move(my_move, &game, game.ply > 1 && with_info);
 
+                    println!("Thought for {:.1}s.", time.elapsed().as_secs_f32());
                     println!("=== Network played  {my_move}");
                     tx.send(Message::Move(my_move)).unwrap();
                     game.play(my_move).unwrap();
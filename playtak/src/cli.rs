@@ -18,10 +18,26 @@ pub struct Args {
     /// Increment in seconds
     #[clap(long, default_value_t = 10)]
     pub increment: u64,
-    /// Time to think per move
-    #[clap(short, long, default_value_t = 10)]
-    pub time_to_think: u64,
+    /// Fixed time to think per move in seconds (uses the clock if not given)
+    #[clap(short, long)]
+    pub time_to_think: Option<u64>,
+    /// Maximum number of rollouts to do while waiting for the opponent
+    #[clap(long, default_value_t = 50_000)]
+    pub ponder_rollout_limit: u64,
+    /// Sample moves using the temperature schedule before this ply,
+    /// to vary the openings
+    #[clap(long, default_value_t = 0)]
+    pub sample_plies: u16,
+    /// Path to a search config file
+    #[clap(short, long)]
+    pub config: Option<String>,
+    /// Override a search parameter, for example `--set batch_size=128`
+    #[clap(long = "set")]
+    pub overrides: Vec<String>,
     /// Disable GPU usage
     #[clap(short, long)]
     pub no_gpu: bool,
+    /// Number of network evaluations to cache (0 disables the cache)
+    #[clap(long, default_value_t = 100_000)]
+    pub cache_size: usize,
 }
@@ -29,8 +29,6 @@ const OPPOSITE_CORNER: [(&str, &str); 4] = [("a1", "f6"), ("a6", "f1"), ("f1", "
 const HUG: [(&str, &str); 4] = [("a1", "b1"), ("a6", "a5"), ("f1", "f2"), ("f6", "e6")];
 const OPENING_BOOK: [(&str, &str); 4] = OPPOSITE_CORNER;
 
-const PONDER_ROLLOUT_LIMIT: u64 = 50_000;
-
 const ANALYSIS_DIR: &str = "_playtak_games";
 const EXAMPLE_DIR: &str = "_examples";
 
@@ -1,10 +1,16 @@
+use std::time::Duration;
+
 use tak::Move;
 use tokio_takconnect::data_types::{Game, GameResult};
 
 #[derive(Debug)]
 pub enum Message {
     GameInfo(Game),
-    MoveRequest,
+    /// Ask for a move, given the time left on our clock and the increment.
+    MoveRequest {
+        remaining: Duration,
+        increment: Duration,
+    },
     Move(Move),
     GameEnded(Option<GameResult>),
 }
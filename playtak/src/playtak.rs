@@ -1,4 +1,7 @@
-use std::{error::Error, time::Duration};
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
 
 use tokio::{
     select,
@@ -67,15 +70,23 @@ async fn run_playtak_game(
 
     println!("Game started! {} vs {}", game_info.white(), game_info.black());
 
+    // The client does not expose the clock updates from the server,
+    // so keep our clock the same way: it runs from the moment it is our turn
+    // until our move is sent, and then the increment is added.
+    let increment = game_info.params().increment();
+    let mut remaining = game_info.params().initial_time();
+
     let mut take_my_turn = seek_as_white;
     loop {
         if take_my_turn {
-            tx.send(Message::MoveRequest)?;
+            let turn_start = Instant::now();
+            tx.send(Message::MoveRequest { remaining, increment })?;
             match rx.recv().await {
                 Some(Message::Move(my_move)) => {
                     if game.play(my_move).await.is_err() {
                         println!("Failed to play move!");
                     }
+                    remaining = remaining.saturating_sub(turn_start.elapsed()) + increment;
                 }
                 Some(Message::GameEnded(_)) => {}
                 None => break Ok(()),
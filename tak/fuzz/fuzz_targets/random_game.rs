@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+fuzz_target!(|data: &[u8]| {
+    let choices: Vec<usize> = data
+        .chunks(4)
+        .map(|chunk| chunk.iter().fold(0, |acc, &byte| acc << 8 | byte as usize))
+        .collect();
+    common::play_and_check::<5>(&choices);
+    common::play_and_check::<6>(&choices);
+});
@@ -0,0 +1,186 @@
+use std::{collections::HashSet, error::Error, fmt::Display, str::FromStr};
+
+use takparse::{Color, Move, Tps};
+
+use crate::{game::Game, game_result::GameResult};
+
+/// A single position together with what a rules engine should say about it.
+/// Records are stored one per line as
+/// `TPS;half_komi;result;moves;reversible_plies`, where the result uses PTN
+/// notation (or `ongoing`) and the moves are a comma-separated list of PTN
+/// moves. Finished positions have an empty move list.
+/// TPS does not include the number of reversible plies, so it is stored
+/// separately. Records without it count from zero.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub tps: Tps,
+    pub half_komi: i8,
+    pub result: GameResult,
+    pub moves: Vec<Move>,
+    pub reversible_plies: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    Result {
+        expected: GameResult,
+        actual: GameResult,
+    },
+    MissingMove(Move),
+    ExtraMove(Move),
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Result { expected, actual } => write!(
+                f,
+                "expected result {} but found {}",
+                result_to_str(*expected),
+                result_to_str(*actual)
+            ),
+            Mismatch::MissingMove(m) => write!(f, "legal move {m} was not generated"),
+            Mismatch::ExtraMove(m) => write!(f, "generated move {m} is not legal"),
+        }
+    }
+}
+
+impl Record {
+    /// Create a record describing the game as seen by this engine.
+    pub fn from_game<const N: usize>(game: &Game<N>) -> Self {
+        let result = game.result();
+        let mut moves = if result == GameResult::Ongoing {
+            game.possible_moves()
+        } else {
+            Vec::new()
+        };
+        moves.sort_by_cached_key(Move::to_string);
+        Record {
+            tps: game.clone().into(),
+            half_komi: game.half_komi,
+            result,
+            moves,
+            reversible_plies: game.reversible_plies,
+        }
+    }
+
+    /// Compare the record against this engine and list all differences.
+    pub fn check<const N: usize>(&self) -> Vec<Mismatch> {
+        let mut game: Game<N> = self.tps.clone().into();
+        game.half_komi = self.half_komi;
+        game.reversible_plies = self.reversible_plies;
+        let actual = Record::from_game(&game);
+
+        let mut mismatches = Vec::new();
+        if result_to_str(self.result) != result_to_str(actual.result) {
+            mismatches.push(Mismatch::Result {
+                expected: self.result,
+                actual: actual.result,
+            });
+        }
+
+        let expected_moves: HashSet<_> = self.moves.iter().collect();
+        let actual_moves: HashSet<_> = actual.moves.iter().collect();
+        mismatches.extend(
+            self.moves
+                .iter()
+                .filter(|m| !actual_moves.contains(m))
+                .map(|m| Mismatch::MissingMove(*m)),
+        );
+        mismatches.extend(
+            actual
+                .moves
+                .iter()
+                .filter(|m| !expected_moves.contains(m))
+                .map(|m| Mismatch::ExtraMove(*m)),
+        );
+        mismatches
+    }
+}
+
+/// Draws are not distinguished by reason, so a record
+/// is still valid when the reversible ply rule changes.
+fn result_to_str(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Winner {
+            color: Color::White,
+            road: true,
+        } => "R-0",
+        GameResult::Winner {
+            color: Color::White,
+            road: false,
+        } => "F-0",
+        GameResult::Winner {
+            color: Color::Black,
+            road: true,
+        } => "0-R",
+        GameResult::Winner {
+            color: Color::Black,
+            road: false,
+        } => "0-F",
+        GameResult::Draw { .. } => "1/2-1/2",
+        GameResult::Ongoing => "ongoing",
+    }
+}
+
+fn result_from_str(s: &str) -> Option<GameResult> {
+    let (color, road) = match s {
+        "R-0" => (Color::White, true),
+        "F-0" => (Color::White, false),
+        "0-R" => (Color::Black, true),
+        "0-F" => (Color::Black, false),
+        "1/2-1/2" => {
+            return Some(GameResult::Draw {
+                reversible_plies: false,
+            })
+        }
+        "ongoing" => return Some(GameResult::Ongoing),
+        _ => return None,
+    };
+    Some(GameResult::Winner { color, road })
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{};{};{};{};{}",
+            self.tps,
+            self.half_komi,
+            result_to_str(self.result),
+            self.moves
+                .iter()
+                .map(Move::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+            self.reversible_plies
+        )
+    }
+}
+
+impl FromStr for Record {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut iter = s.trim().split(';');
+
+        let tps = iter.next().ok_or("missing tps")?.parse()?;
+        let half_komi = iter.next().ok_or("missing half komi")?.parse()?;
+        let result = result_from_str(iter.next().ok_or("missing result")?).ok_or("unknown result")?;
+        let moves = iter
+            .next()
+            .ok_or("missing moves")?
+            .split_terminator(',')
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        let reversible_plies = iter.next().map(str::parse).transpose()?.unwrap_or_default();
+
+        Ok(Record {
+            tps,
+            half_komi,
+            result,
+            moves,
+            reversible_plies,
+        })
+    }
+}
@@ -89,3 +89,30 @@ impl Display for TakeError {
         }
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NotationError {
+    Malformed,
+    BadSquare,
+    BadPiece,
+    BadDrop,
+    NotStraight,
+    DropCount,
+    OffBoard,
+}
+
+impl Display for NotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            NotationError::Malformed => "move is not in a recognized format",
+            NotationError::BadSquare => "could not parse square",
+            NotationError::BadPiece => "unknown piece type",
+            NotationError::BadDrop => "drop counts must be positive integers",
+            NotationError::NotStraight => "spread must move in a straight line",
+            NotationError::DropCount => "number of drops does not match the spread distance",
+            NotationError::OffBoard => "spread leaves the board",
+        })
+    }
+}
+
+impl Error for NotationError {}
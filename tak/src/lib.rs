@@ -1,16 +1,20 @@
 mod board;
+mod conformance;
 mod error;
 mod game;
 mod game_result;
 mod move_gen;
+mod notation;
 mod symm;
 mod tile;
 mod tps;
 
 pub use board::Board;
+pub use conformance::{Mismatch, Record};
 pub use error::*;
 pub use game::{default_starting_stones, Game};
 pub use game_result::GameResult;
+pub use notation::{from_playtak, to_playtak};
 pub use symm::Symmetry;
 pub use takparse::{self, Color, Direction, Move, MoveKind, Pattern, Piece, Square};
 pub use tile::Tile;
@@ -0,0 +1,180 @@
+use std::ops::Not;
+
+use takparse::{Color, Direction, Move, MoveKind, Pattern, Piece, Square};
+
+use crate::{error::NotationError, game::Game};
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+fn piece_name(piece: Piece) -> &'static str {
+    match piece {
+        Piece::Flat => "flat",
+        Piece::Wall => "wall",
+        Piece::Cap => "capstone",
+    }
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+        Direction::Left => "left",
+        Direction::Right => "right",
+    }
+}
+
+/// Format a square the way the playtak server does (uppercase column).
+fn playtak_square(square: Square) -> String {
+    square.to_string().to_uppercase()
+}
+
+// Largest board size supported by playtak.
+const MAX_SIZE: u8 = 8;
+
+fn parse_playtak_square(s: &str) -> Result<Square, NotationError> {
+    let mut chars = s.chars();
+    let column = chars
+        .next()
+        .filter(char::is_ascii_alphabetic)
+        .ok_or(NotationError::BadSquare)?;
+    let row: u8 = chars.as_str().parse().map_err(|_| NotationError::BadSquare)?;
+    let column = column.to_ascii_lowercase() as u8 - b'a';
+    if row == 0 || row > MAX_SIZE || column >= MAX_SIZE {
+        return Err(NotationError::BadSquare);
+    }
+    Ok(Square::new(column, row - 1))
+}
+
+/// Convert a move into playtak server notation.
+/// Placements look like `P A1`, `P A1 W`, or `P A1 C`
+/// and spreads look like `M A1 A3 1 1`.
+/// Since the board size is not known, spreads are only rejected
+/// when they would leave the largest board.
+pub fn to_playtak(my_move: Move) -> Result<String, NotationError> {
+    let square = my_move.square();
+    match my_move.kind() {
+        MoveKind::Place(piece) => {
+            let suffix = match piece {
+                Piece::Flat => "",
+                Piece::Wall => " W",
+                Piece::Cap => " C",
+            };
+            Ok(format!("P {}{suffix}", playtak_square(square)))
+        }
+        MoveKind::Spread(direction, pattern) => {
+            let drops: Vec<_> = pattern.drop_counts().map(|drop| drop.to_string()).collect();
+            // Step manually since we do not know the board size here.
+            let distance = drops.len() as u8;
+            let (column, row) = (square.column(), square.row());
+            let (column, row) = match direction {
+                Direction::Up => (Some(column), row.checked_add(distance)),
+                Direction::Down => (Some(column), row.checked_sub(distance)),
+                Direction::Left => (column.checked_sub(distance), Some(row)),
+                Direction::Right => (column.checked_add(distance), Some(row)),
+            };
+            let end = match (column, row) {
+                (Some(column), Some(row)) if column < MAX_SIZE && row < MAX_SIZE => Square::new(column, row),
+                _ => return Err(NotationError::OffBoard),
+            };
+            Ok(format!(
+                "M {} {} {}",
+                playtak_square(square),
+                playtak_square(end),
+                drops.join(" ")
+            ))
+        }
+    }
+}
+
+/// Parse a move from playtak server notation.
+pub fn from_playtak(s: &str) -> Result<Move, NotationError> {
+    let words: Vec<_> = s.split_whitespace().collect();
+    match words.as_slice() {
+        ["P", square, rest @ ..] => {
+            let piece = match rest {
+                [] => Piece::Flat,
+                ["W"] => Piece::Wall,
+                ["C"] => Piece::Cap,
+                _ => return Err(NotationError::BadPiece),
+            };
+            Ok(Move::new(parse_playtak_square(square)?, MoveKind::Place(piece)))
+        }
+        ["M", from, to, drops @ ..] => {
+            let from = parse_playtak_square(from)?;
+            let to = parse_playtak_square(to)?;
+            let (dx, dy) = (
+                to.column() as i32 - from.column() as i32,
+                to.row() as i32 - from.row() as i32,
+            );
+            let (direction, distance) = match (dx, dy) {
+                (0, dy) if dy > 0 => (Direction::Up, dy),
+                (0, dy) if dy < 0 => (Direction::Down, -dy),
+                (dx, 0) if dx > 0 => (Direction::Right, dx),
+                (dx, 0) if dx < 0 => (Direction::Left, -dx),
+                _ => return Err(NotationError::NotStraight),
+            };
+            let drops = drops
+                .iter()
+                .map(|drop| match drop.parse::<u32>() {
+                    Ok(0) | Err(_) => Err(NotationError::BadDrop),
+                    Ok(drop) => Ok(drop),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if drops.len() != distance as usize {
+                return Err(NotationError::DropCount);
+            }
+            let pattern: Pattern = drops.into_iter().collect();
+            Ok(Move::new(from, MoveKind::Spread(direction, pattern)))
+        }
+        _ => Err(NotationError::Malformed),
+    }
+}
+
+impl<const N: usize> Game<N> {
+    /// Describe a move in plain English, for example
+    /// "white capstone smashes wall at c3".
+    /// The move is described relative to the current position,
+    /// so it should be called before the move is played.
+    pub fn describe_move(&self, my_move: Move) -> String {
+        let color = color_name(self.to_move);
+        let square = my_move.square();
+        match my_move.kind() {
+            MoveKind::Place(piece) if self.is_swapped() => format!(
+                "{color} places {} {} at {square}",
+                color_name(self.to_move.not()),
+                piece_name(piece)
+            ),
+            MoveKind::Place(piece) => format!("{color} places {} at {square}", piece_name(piece)),
+            MoveKind::Spread(direction, pattern) => {
+                let piece = self.board.get(square).map(|tile| tile.piece).unwrap_or_default();
+                let count = pattern.count_pieces();
+                let distance = pattern.drop_counts().count() as u8;
+                let mut end = Some(square);
+                for _ in 0..distance {
+                    end = end.and_then(|s| s.checked_step(direction, N as u8));
+                }
+                let smash = piece == Piece::Cap
+                    && end.and_then(|s| self.board.get(s)).map(|tile| tile.piece) == Some(Piece::Wall);
+                if let (true, Some(end)) = (smash, end) {
+                    format!("{color} capstone smashes wall at {end}")
+                } else {
+                    let stack = if count == 1 {
+                        piece_name(piece).to_string()
+                    } else {
+                        format!("stack of {count} ({} on top)", piece_name(piece))
+                    };
+                    format!(
+                        "{color} moves {stack} from {square} {} over {distance} square{}",
+                        direction_name(direction),
+                        if distance == 1 { "" } else { "s" }
+                    )
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Invariant checks shared by the property tests and the fuzz targets.
+
+use std::ops::Not;
+
+use tak::{takparse::Tps, *};
+
+/// Play a game on an empty board using `choices` to pick moves.
+/// Every ply checks move generation, move rejection, symmetries,
+/// reserve counts, and TPS conversion.
+pub fn play_and_check<const N: usize>(choices: &[usize]) {
+    let mut game = Game::<N>::default();
+    let mut choices = choices.iter().copied();
+    while game.result() == GameResult::Ongoing {
+        let (pick, probe) = match (choices.next(), choices.next()) {
+            (Some(pick), Some(probe)) => (pick, probe),
+            _ => break,
+        };
+
+        let moves = game.possible_moves();
+        assert!(!moves.is_empty(), "ongoing game has no possible moves");
+        check_possible_moves(&game, &moves);
+        check_rejection(&game, arbitrary_move::<N>(probe), &moves);
+
+        let my_move = moves[pick % moves.len()];
+        check_symmetry(&game, my_move);
+        game.play(my_move).unwrap();
+
+        check_reserves(&game);
+        check_tps(&game);
+    }
+}
+
+/// Every generated move must be accepted by `play`.
+pub fn check_possible_moves<const N: usize>(game: &Game<N>, moves: &[Move]) {
+    for &my_move in moves {
+        if let Err(err) = game.clone().play(my_move) {
+            panic!("generated move {my_move} was rejected: {err}");
+        }
+    }
+}
+
+/// A move must fail with exactly the error predicted by the rules,
+/// and any move that the rules allow must also be generated.
+pub fn check_rejection<const N: usize>(game: &Game<N>, my_move: Move, moves: &[Move]) {
+    let expected = expected_error(game, my_move);
+    let actual = game.clone().play(my_move).err();
+    assert_eq!(actual, expected, "unexpected outcome for {my_move}");
+    if expected.is_none() {
+        assert!(moves.contains(&my_move), "legal move {my_move} was not generated");
+    }
+}
+
+/// Playing a transformed move on a transformed game must match
+/// transforming the game after playing the move.
+pub fn check_symmetry<const N: usize>(game: &Game<N>, my_move: Move) {
+    let mut played = game.clone();
+    played.play(my_move).unwrap();
+    let expected = played.symmetries();
+    let moves = Symmetry::<N>::symmetries(my_move);
+    for ((mut symmetry, m), e) in game.clone().symmetries().into_iter().zip(moves).zip(expected) {
+        symmetry.play(m).unwrap();
+        assert_eq!(
+            symmetry.board, e.board,
+            "symmetry does not commute with {my_move}"
+        );
+        assert_eq!(symmetry.result(), e.result());
+    }
+}
+
+/// Pieces on the board plus reserves must add up to the starting reserves.
+pub fn check_reserves<const N: usize>(game: &Game<N>) {
+    let (mut white_stones, mut white_caps) = (0, 0);
+    let (mut black_stones, mut black_caps) = (0, 0);
+    for x in 0..N {
+        for y in 0..N {
+            let tile = &game.board[Square::new(x as u8, y as u8)];
+            for (i, &color) in tile.stack.iter().enumerate() {
+                let cap = i + 1 == tile.size() && tile.piece == Piece::Cap;
+                match (color, cap) {
+                    (Color::White, false) => white_stones += 1,
+                    (Color::White, true) => white_caps += 1,
+                    (Color::Black, false) => black_stones += 1,
+                    (Color::Black, true) => black_caps += 1,
+                }
+            }
+        }
+    }
+    let (stones, caps) = default_starting_stones(N);
+    assert_eq!(
+        white_stones + game.white_stones,
+        stones,
+        "white stones not conserved"
+    );
+    assert_eq!(white_caps + game.white_caps, caps, "white caps not conserved");
+    assert_eq!(
+        black_stones + game.black_stones,
+        stones,
+        "black stones not conserved"
+    );
+    assert_eq!(black_caps + game.black_caps, caps, "black caps not conserved");
+}
+
+/// Converting to TPS and back must preserve the position.
+pub fn check_tps<const N: usize>(game: &Game<N>) {
+    let tps: Tps = game.clone().into();
+    let tps_game: Game<N> = tps.into();
+    assert_eq!(game.board, tps_game.board, "board does not equal");
+    assert_eq!(game.to_move, tps_game.to_move, "to_move does not equal");
+    assert_eq!(game.ply, tps_game.ply, "ply does not equal");
+    assert_eq!(
+        game.white_stones, tps_game.white_stones,
+        "white stones do not equal"
+    );
+    assert_eq!(game.white_caps, tps_game.white_caps, "white caps do not equal");
+    assert_eq!(
+        game.black_stones, tps_game.black_stones,
+        "black stones do not equal"
+    );
+    assert_eq!(game.black_caps, tps_game.black_caps, "black caps do not equal");
+}
+
+/// Build a move (possibly illegal or off the board) from arbitrary bits.
+pub fn arbitrary_move<const N: usize>(mut bits: usize) -> Move {
+    let mut take = |n: usize| {
+        let value = bits % n;
+        bits /= n;
+        value
+    };
+    let square = Square::new(take(N + 1) as u8, take(N + 1) as u8);
+    let kind = if take(2) == 0 {
+        MoveKind::Place([Piece::Flat, Piece::Wall, Piece::Cap][take(3)])
+    } else {
+        let direction = [Direction::Up, Direction::Down, Direction::Left, Direction::Right][take(4)];
+        let mut drops = Vec::new();
+        let mut carry = 0;
+        for _ in 0..=take(N) {
+            let drop = 1 + take(3) as u32;
+            // Patterns cannot describe more than 8 pieces.
+            if carry + drop > 8 {
+                break;
+            }
+            carry += drop;
+            drops.push(drop);
+        }
+        MoveKind::Spread(direction, drops.into_iter().collect())
+    };
+    Move::new(square, kind)
+}
+
+/// Predict the error `play` should return for a move, following the rules
+/// in the same order that the game checks them.
+pub fn expected_error<const N: usize>(game: &Game<N>, my_move: Move) -> Option<PlayError> {
+    let square = my_move.square();
+    let tile = match game.board.get(square) {
+        Some(tile) => tile,
+        None => return Some(PlayError::OutOfBounds),
+    };
+    let opening = game.ply < 2;
+    let color = if opening { game.to_move.not() } else { game.to_move };
+    let (stones, caps) = match game.to_move {
+        Color::White => (game.white_stones, game.white_caps),
+        Color::Black => (game.black_stones, game.black_caps),
+    };
+
+    match my_move.kind() {
+        MoveKind::Place(piece) => {
+            if !tile.is_empty() {
+                Some(PlayError::AlreadyOccupied)
+            } else if piece == Piece::Cap && caps == 0 {
+                Some(PlayError::NoCapstone)
+            } else if piece != Piece::Cap && stones == 0 {
+                Some(PlayError::NoStones)
+            } else if opening && piece != Piece::Flat {
+                Some(PlayError::OpeningNonFlat)
+            } else {
+                None
+            }
+        }
+        MoveKind::Spread(direction, pattern) => {
+            let (top_piece, top_color) = match tile.top() {
+                Some(top) => top,
+                None => return Some(PlayError::EmptySquare),
+            };
+            if top_color != color {
+                return Some(PlayError::StackNotOwned);
+            }
+            let carry = pattern.count_pieces() as usize;
+            if carry > N {
+                return Some(PlayError::TakeError(TakeError::CarryLimit));
+            } else if carry > tile.size() {
+                return Some(PlayError::TakeError(TakeError::StackSize(tile.size())));
+            }
+
+            let drops: Vec<_> = pattern.drop_counts().collect();
+            let mut pos = square;
+            for (i, &drop) in drops.iter().enumerate() {
+                pos = match pos.checked_step(direction, N as u8) {
+                    Some(next) => next,
+                    None => return Some(PlayError::SpreadOutOfBounds),
+                };
+                let smash = i + 1 == drops.len() && drop == 1 && top_piece == Piece::Cap;
+                match game.board[pos].piece {
+                    Piece::Cap => return Some(PlayError::StackError(StackError::Cap)),
+                    Piece::Wall if !smash => return Some(PlayError::StackError(StackError::Wall)),
+                    _ => {}
+                }
+            }
+            None
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use tak::*;
+
+#[test]
+fn record_round_trip() -> Result<(), PlayError> {
+    let game = Game::<5>::from_ptn_moves(&["a1", "e5", "Cc3", "Sd3", "c4", "d3+"])?;
+    let record = Record::from_game(&game);
+    let parsed: Record = record.to_string().parse().unwrap();
+    assert_eq!(parsed.moves, record.moves);
+    assert_eq!(parsed.result, GameResult::Ongoing);
+    assert!(parsed.check::<5>().is_empty());
+    Ok(())
+}
+
+#[test]
+fn record_mismatch() {
+    let game = Game::<3>::from_ptn_moves(&["a3", "c1", "c2", "c3", "b3", "b2", "b1", "a1", "a2"]).unwrap();
+    let mut record = Record::from_game(&game);
+    assert!(record.moves.is_empty());
+    record.result = GameResult::Ongoing;
+    record.moves.push("a1".parse().unwrap());
+    assert_eq!(record.check::<3>(), vec![
+        Mismatch::Result {
+            expected: GameResult::Ongoing,
+            actual: GameResult::Winner {
+                color: Color::White,
+                road: false
+            }
+        },
+        Mismatch::MissingMove("a1".parse().unwrap()),
+    ]);
+}
+
+#[test]
+fn record_reversible_draw() -> Result<(), PlayError> {
+    let mut game = Game::<5>::from_ptn_moves(&["a1", "e5", "e5<", "a1>"])?;
+    game.reversible_plies = 50;
+    let record = Record::from_game(&game);
+    assert!(matches!(record.result, GameResult::Draw { .. }));
+    let parsed: Record = record.to_string().parse().unwrap();
+    assert_eq!(parsed.reversible_plies, 50);
+    assert!(parsed.check::<5>().is_empty());
+
+    // Records without the reversible plies still parse.
+    let parsed: Record = "x5/x5/x5/x5/x5 1 1;0;ongoing;".parse().unwrap();
+    assert_eq!(parsed.reversible_plies, 0);
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use tak::*;
+
+#[test]
+fn playtak_place() -> Result<(), NotationError> {
+    assert_eq!(to_playtak("a1".parse().unwrap())?, "P A1");
+    assert_eq!(to_playtak("Sc3".parse().unwrap())?, "P C3 W");
+    assert_eq!(to_playtak("Cf6".parse().unwrap())?, "P F6 C");
+    assert_eq!(from_playtak("P C3 W")?, "Sc3".parse().unwrap());
+    Ok(())
+}
+
+#[test]
+fn playtak_spread() -> Result<(), NotationError> {
+    assert_eq!(to_playtak("2a1+11".parse().unwrap())?, "M A1 A3 1 1");
+    assert_eq!(to_playtak("3e4<21".parse().unwrap())?, "M E4 C4 2 1");
+    assert_eq!(from_playtak("M A1 A3 1 1")?, "2a1+11".parse().unwrap());
+    assert_eq!(from_playtak("M D2 D1 1")?, "d2-".parse().unwrap());
+    Ok(())
+}
+
+#[test]
+fn playtak_errors() {
+    assert_eq!(from_playtak("X A1"), Err(NotationError::Malformed));
+    assert_eq!(from_playtak("P A1 Q"), Err(NotationError::BadPiece));
+    assert_eq!(from_playtak("M A1 B2 1"), Err(NotationError::NotStraight));
+    assert_eq!(from_playtak("M A1 A3 2"), Err(NotationError::DropCount));
+    assert_eq!(to_playtak("a1-".parse().unwrap()), Err(NotationError::OffBoard));
+    assert_eq!(
+        to_playtak("2b1<11".parse().unwrap()),
+        Err(NotationError::OffBoard)
+    );
+    assert_eq!(to_playtak("h1>".parse().unwrap()), Err(NotationError::OffBoard));
+    assert_eq!(from_playtak("P Z9"), Err(NotationError::BadSquare));
+    assert_eq!(from_playtak("P I1"), Err(NotationError::BadSquare));
+    assert_eq!(from_playtak("M A9 A8 1"), Err(NotationError::BadSquare));
+    assert_eq!(from_playtak("P H8"), Ok("h8".parse().unwrap()));
+}
+
+#[test]
+fn playtak_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let game = Game::<6>::from_ptn_moves(&["a1", "f6", "Cc3", "Sd3", "c4", "d3+"])?;
+    for my_move in game.possible_moves() {
+        assert_eq!(from_playtak(&to_playtak(my_move)?), Ok(my_move));
+    }
+    Ok(())
+}
+
+#[test]
+fn describe_smash() -> Result<(), PlayError> {
+    let game = Game::<5>::from_ptn_moves(&["a1", "e5", "Cc3", "Sd3"])?;
+    assert_eq!(
+        game.describe_move("c3>".parse().unwrap()),
+        "white capstone smashes wall at d3"
+    );
+    assert_eq!(
+        game.describe_move("c2".parse().unwrap()),
+        "white places flat at c2"
+    );
+    Ok(())
+}
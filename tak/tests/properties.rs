@@ -0,0 +1,22 @@
+use proptest::prelude::*;
+
+mod common;
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn random_games_3(choices in prop::collection::vec(any::<usize>(), 0..200)) {
+        common::play_and_check::<3>(&choices);
+    }
+
+    #[test]
+    fn random_games_5(choices in prop::collection::vec(any::<usize>(), 0..400)) {
+        common::play_and_check::<5>(&choices);
+    }
+
+    #[test]
+    fn random_games_6(choices in prop::collection::vec(any::<usize>(), 0..400)) {
+        common::play_and_check::<6>(&choices);
+    }
+}
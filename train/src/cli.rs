@@ -10,7 +10,23 @@ pub struct Args {
     pub folder: Option<String>,
     /// Paths to example files
     pub examples: Vec<String>,
+    /// Number of network evaluations to cache in self-play and pit games
+    /// (0 disables the cache)
+    #[clap(long, default_value_t = 0)]
+    pub cache_size: usize,
+    /// Give a new model a win/draw/loss value head
+    #[clap(long)]
+    pub wdl: bool,
+    /// Give a new model heads for the auxiliary training targets
+    #[clap(long)]
+    pub auxiliary: bool,
     /// Disable GPU usage
     #[clap(short, long)]
     pub no_gpu: bool,
+    /// Path to a search config file
+    #[clap(short, long)]
+    pub config: Option<String>,
+    /// Override a search parameter, for example `--set rollouts=800`
+    #[clap(long = "set")]
+    pub overrides: Vec<String>,
 }
@@ -4,13 +4,12 @@ use std::{
 };
 
 #[allow(unused_imports)]
-use alpha_tak::{sys_time, use_cuda, Example, Net5, Net6, Network};
+use alpha_tak::{sys_time, use_cuda, CachedNetwork, Example, Heads, Net5, Net6, Network, SearchConfig};
 use clap::Parser;
 use cli::Args;
 use mimalloc::MiMalloc;
 use pit::pit;
-// use self_play::self_play;
-use self_play::self_play_parallel;
+use self_play::self_play;
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
@@ -43,15 +42,20 @@ fn main() {
     train::<6, Net6>(args);
 }
 
-fn get_network<const N: usize, NET: Network<N>>(model_path: Option<String>) -> NET {
+fn get_network<const N: usize, NET: Network<N>>(model_path: Option<String>, heads: Heads) -> NET {
     match &model_path {
         Some(m) if m != "random" => NET::load(m).unwrap_or_else(|_| panic!("couldn't load model at {m}")),
-        _ => NET::default(),
+        _ => NET::with_heads(heads),
     }
 }
 
 fn train<const N: usize, NET: Network<N>>(mut args: Args) -> ! {
-    let network = get_network::<N, NET>(args.model_path);
+    let network = get_network::<N, NET>(args.model_path, Heads {
+        wdl: args.wdl,
+        auxiliary: args.auxiliary,
+    });
+    let config = SearchConfig::from_args(args.config.as_deref(), &args.overrides)
+        .unwrap_or_else(|e| panic!("couldn't load search config: {e}"));
 
     let mut examples = Vec::new();
 
@@ -79,22 +83,27 @@ fn train<const N: usize, NET: Network<N>>(mut args: Args) -> ! {
         );
     }
 
-    training_loop(network, examples)
+    training_loop(CachedNetwork::new(network, args.cache_size), examples, &config)
 }
 
-fn training_loop<const N: usize, NET: Network<N>>(mut network: NET, mut examples: Vec<Example<N>>) -> ! {
+fn training_loop<const N: usize, NET: Network<N>>(
+    mut network: CachedNetwork<NET>,
+    mut examples: Vec<Example<N>>,
+    config: &SearchConfig,
+) -> ! {
+    let cache_size = network.capacity();
     loop {
         if !examples.is_empty() {
             // Train on examples.
             let new_network = {
-                let mut nn = copy(&network);
+                let mut nn = CachedNetwork::new(copy(network.inner()), cache_size);
                 nn.train(&examples);
                 nn
             };
 
             // Run pit games.
             println!("pitting two networks against each other");
-            let results = pit(&new_network, &network);
+            let results = pit(&new_network, &network, config);
             println!("{results:?}");
 
             // Save new network if it is better.
@@ -117,7 +126,10 @@ fn training_loop<const N: usize, NET: Network<N>>(mut network: NET, mut examples
 
         // Do self-play to get new examples.
         println!("starting self-play");
-        let new_examples = self_play_parallel(&network);
+        let new_examples = self_play(&network, config);
+        if cache_size > 0 {
+            println!("cache hit rate: {:.3}", network.hit_rate());
+        }
         examples.extend(new_examples.into_iter())
     }
 }
@@ -1,4 +1,14 @@
-use alpha_tak::{Network, Player};
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+        Mutex,
+    },
+    thread::spawn,
+    time::Duration,
+};
+
+use alpha_tak::{inference_channel, InferenceClient, Network, Player, SearchConfig};
 use rand::{prelude::SliceRandom, thread_rng, Rng};
 use tak::*;
 
@@ -6,29 +16,85 @@ const PIT_GAMES: u32 = 128;
 const BATCH_SIZE: u32 = 16;
 const ROLLOUTS: u32 = 50;
 
+const PIT_THREADS: usize = 16;
+const MAX_LATENCY: Duration = Duration::from_millis(5);
+
 const RANDOM_PLIES: u32 = 2;
 
 // const NOISE_ALPHA: f32 = 0.4;
 // const NOISE_RATIO: f32 = 0.2;
 // const NOISE_PLIES: u16 = 30;
 
-pub fn pit<const N: usize, NET: Network<N>>(new: &NET, old: &NET) -> PitResult {
-    let mut result = PitResult::default();
+/// Play pit games on many threads at once. Each network is shared
+/// through its own inference server, which are both served by this thread.
+pub fn pit<const N: usize, NET: Network<N>>(new: &NET, old: &NET, config: &SearchConfig) -> PitResult {
+    let config = SearchConfig {
+        batch_size: BATCH_SIZE,
+        rollouts: ROLLOUTS,
+        ..config.clone()
+    };
+    let result = Arc::new(Mutex::new(PitResult::default()));
+    let games_started = Arc::new(AtomicU32::new(0));
+
+    let max_batch = PIT_THREADS * BATCH_SIZE as usize;
+    let (mut new_server, new_client) = inference_channel(max_batch, MAX_LATENCY);
+    let (mut old_server, old_client) = inference_channel(max_batch, MAX_LATENCY);
+    let workers: Vec<_> = (0..PIT_THREADS)
+        .map(|_| {
+            let new_client = new_client.clone();
+            let old_client = old_client.clone();
+            let config = config.clone();
+            let result = result.clone();
+            let games_started = games_started.clone();
+            spawn(move || play_pit_games(new_client, old_client, config, result, games_started))
+        })
+        .collect();
+    // The servers stop once every worker has dropped its clients.
+    drop((new_client, old_client));
+    let (mut new_open, mut old_open) = (true, true);
+    while new_open || old_open {
+        if new_open {
+            new_open = new_server.serve_for(new, MAX_LATENCY);
+        }
+        if old_open {
+            old_open = old_server.serve_for(old, MAX_LATENCY);
+        }
+    }
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    Arc::try_unwrap(result).unwrap().into_inner().unwrap()
+}
 
+fn play_pit_games<const N: usize>(
+    new: InferenceClient<N>,
+    old: InferenceClient<N>,
+    config: SearchConfig,
+    result: Arc<Mutex<PitResult>>,
+    games_started: Arc<AtomicU32>,
+) {
     let mut rng = thread_rng();
-    for i in 0..PIT_GAMES {
-        if result.wins > (PIT_GAMES + PIT_GAMES / 10) || result.losses > (PIT_GAMES - PIT_GAMES / 10) {
-            println!("breaking early because result is already known");
+    loop {
+        let i = games_started.fetch_add(1, Ordering::Relaxed);
+        if i >= PIT_GAMES {
             break;
         }
+        {
+            let result = result.lock().unwrap();
+            if result.wins > (PIT_GAMES + PIT_GAMES / 10) || result.losses > (PIT_GAMES - PIT_GAMES / 10) {
+                println!("breaking early because result is already known");
+                break;
+            }
+        }
 
         println!("pit game {i}/{PIT_GAMES}");
         let mut opening = Vec::new();
         for color in [Color::White, Color::Black] {
             let mut game = Game::with_komi(2);
 
-            let mut new_player = Player::new(new, BATCH_SIZE, false, false, &game);
-            let mut old_player = Player::new(old, BATCH_SIZE, false, false, &game);
+            let mut new_player = Player::new(&new, config.clone(), false, false, &game);
+            let mut old_player = Player::new(&old, config.clone(), false, false, &game);
 
             // Generate an opening opening.
             if opening.is_empty() {
@@ -76,9 +142,9 @@ pub fn pit<const N: usize, NET: Network<N>>(new: &NET, old: &NET) -> PitResult {
                     &mut old_player
                 };
                 // if game.ply < NOISE_PLIES {
-                //     to_move.add_noise(NOISE_ALPHA, NOISE_RATIO, &game);
+                //     to_move.add_noise(&game);
                 // }
-                for _ in 0..ROLLOUTS {
+                for _ in 0..config.rollouts {
                     to_move.rollout(&game);
                 }
                 let my_move = to_move.pick_move(true);
@@ -88,11 +154,9 @@ pub fn pit<const N: usize, NET: Network<N>>(new: &NET, old: &NET) -> PitResult {
             }
             println!("{:?} in {} plies as {color}", game.result(), game.ply);
 
-            result.update(game.result(), color);
+            result.lock().unwrap().update(game.result(), color);
         }
     }
-
-    result
 }
 
 #[derive(Debug, Default)]